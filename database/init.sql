@@ -1,6 +1,73 @@
+-- Keys are only stored as SHA-256 hashes, see `url-shortener create-api-key`.
+CREATE TABLE api_key
+(
+    id         bigserial PRIMARY KEY,
+    name       text        NOT NULL,
+    key_hash   bytea       NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    revoked_at timestamptz
+);
+
 CREATE TABLE link
 (
     id                 text PRIMARY KEY,
     url                text  NOT NULL,
+    expires_at         timestamptz,
+    max_clicks         integer CHECK (max_clicks > 0),
+    remaining_clicks   integer CHECK (remaining_clicks >= 0),
+    owner              bigint REFERENCES api_key (id) ON DELETE SET NULL,
+    -- HTTP status of the redirect, the server default is used when NULL.
+    redirect_type      smallint CHECK (redirect_type IN (301, 302, 307, 308)),
+    -- Whether `/{id}/extra/path` appends `/extra/path` to the destination.
+    forward_path       boolean     NOT NULL DEFAULT false,
+    -- How the request query string is merged into the destination's, see `QueryForwarding`.
+    forward_query      text        NOT NULL DEFAULT 'off'
+        CHECK (forward_query IN ('off', 'prefer_stored', 'prefer_incoming', 'append')),
+    -- Argon2 PHC string, visitors have to enter the password before being redirected.
+    password_hash      text,
+    created_at         timestamptz NOT NULL DEFAULT now(),
+    -- Incremented on every update, exposed as the link's ETag.
+    version            bigint      NOT NULL DEFAULT 1,
+    -- Set when the link is moved to the trash, the alias stays taken until it is purged.
+    deleted_at         timestamptz,
     development_fields jsonb NOT NULL DEFAULT '{}'
 );
+
+CREATE INDEX link_expires_at_idx ON link (expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX link_created_at_idx ON link (created_at, id);
+CREATE INDEX link_owner_idx ON link (owner);
+CREATE INDEX link_deleted_at_idx ON link (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Expired links moved out of `link` by the sweeper, `data` holds the full former row with its
+-- `revisions` and `clicks` as arrays.
+CREATE TABLE link_archive
+(
+    id          text        NOT NULL,
+    data        jsonb       NOT NULL,
+    archived_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE click
+(
+    id              bigserial PRIMARY KEY,
+    link_id         text        NOT NULL REFERENCES link (id) ON DELETE CASCADE,
+    clicked_at      timestamptz NOT NULL,
+    referer         text,
+    user_agent      text,
+    client_ip       inet,
+    accept_language text,
+    country         text
+);
+
+CREATE INDEX click_link_id_clicked_at_idx ON click (link_id, clicked_at);
+
+-- Every destination a link pointed to, written in the same transaction as the link change.
+CREATE TABLE link_revision
+(
+    link_id    text        NOT NULL REFERENCES link (id) ON DELETE CASCADE,
+    revision   integer     NOT NULL,
+    url        text        NOT NULL,
+    author     bigint REFERENCES api_key (id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (link_id, revision)
+);
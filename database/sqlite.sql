@@ -0,0 +1,74 @@
+-- SQLite counterpart of `init.sql`, created by the server on startup when `DB_CONNECTION` is a
+-- `sqlite://` path. Timestamps are microseconds since the Unix epoch, booleans are 0 or 1.
+CREATE TABLE IF NOT EXISTS api_key
+(
+    id         INTEGER PRIMARY KEY,
+    name       TEXT    NOT NULL,
+    key_hash   BLOB    NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('subsec') * 1000000 AS INTEGER)),
+    revoked_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS link
+(
+    id                 TEXT    NOT NULL PRIMARY KEY,
+    url                TEXT    NOT NULL,
+    expires_at         INTEGER,
+    max_clicks         INTEGER CHECK (max_clicks > 0),
+    remaining_clicks   INTEGER CHECK (remaining_clicks >= 0),
+    owner              INTEGER REFERENCES api_key (id) ON DELETE SET NULL,
+    -- HTTP status of the redirect, the server default is used when NULL.
+    redirect_type      INTEGER CHECK (redirect_type IN (301, 302, 307, 308)),
+    -- Whether `/{id}/extra/path` appends `/extra/path` to the destination.
+    forward_path       INTEGER NOT NULL DEFAULT 0,
+    -- How the request query string is merged into the destination's, see `QueryForwarding`.
+    forward_query      TEXT    NOT NULL DEFAULT 'off'
+        CHECK (forward_query IN ('off', 'prefer_stored', 'prefer_incoming', 'append')),
+    -- Argon2 PHC string, visitors have to enter the password before being redirected.
+    password_hash      TEXT,
+    created_at         INTEGER NOT NULL DEFAULT (CAST(unixepoch('subsec') * 1000000 AS INTEGER)),
+    -- Incremented on every update, exposed as the link's ETag.
+    version            INTEGER NOT NULL DEFAULT 1,
+    -- Set when the link is moved to the trash, the alias stays taken until it is purged.
+    deleted_at         INTEGER,
+    development_fields TEXT    NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS link_expires_at_idx ON link (expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS link_created_at_idx ON link (created_at, id);
+CREATE INDEX IF NOT EXISTS link_owner_idx ON link (owner);
+CREATE INDEX IF NOT EXISTS link_deleted_at_idx ON link (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Expired links moved out of `link` by the sweeper, `data` holds the full former row as JSON with
+-- its `revisions` and `clicks` as arrays.
+CREATE TABLE IF NOT EXISTS link_archive
+(
+    id          TEXT    NOT NULL,
+    data        TEXT    NOT NULL,
+    archived_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('subsec') * 1000000 AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS click
+(
+    id              INTEGER PRIMARY KEY,
+    link_id         TEXT    NOT NULL REFERENCES link (id) ON DELETE CASCADE,
+    clicked_at      INTEGER NOT NULL,
+    referer         TEXT,
+    user_agent      TEXT,
+    client_ip       TEXT,
+    accept_language TEXT,
+    country         TEXT
+);
+
+CREATE INDEX IF NOT EXISTS click_link_id_clicked_at_idx ON click (link_id, clicked_at);
+
+-- Every destination a link pointed to, written in the same transaction as the link change.
+CREATE TABLE IF NOT EXISTS link_revision
+(
+    link_id    TEXT    NOT NULL REFERENCES link (id) ON DELETE CASCADE,
+    revision   INTEGER NOT NULL,
+    url        TEXT    NOT NULL,
+    author     INTEGER REFERENCES api_key (id) ON DELETE SET NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (link_id, revision)
+);
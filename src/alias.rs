@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure};
+use rand::Rng;
+
+use crate::error::Violation;
+
+pub const BASE62: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+pub const DEFAULT_LENGTH: usize = 7;
+pub const DEFAULT_CHARSET: &str =
+    "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_";
+pub const DEFAULT_MIN_LENGTH: usize = 3;
+pub const DEFAULT_MAX_LENGTH: usize = 64;
+
+/// Generates random aliases for links created without an explicit `id`.
+#[derive(Clone, Debug)]
+pub struct AliasGenerator {
+    alphabet: Vec<char>,
+    length: usize,
+}
+
+impl AliasGenerator {
+    pub fn new(alphabet: &str, length: usize) -> anyhow::Result<Self> {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        ensure!(alphabet.len() >= 2, "Alias alphabet needs at least two characters");
+        ensure!(
+            alphabet.iter().enumerate().all(|(i, c)| !alphabet[..i].contains(c)),
+            "Alias alphabet contains duplicate characters"
+        );
+        ensure!(length > 0, "Alias length must be greater than zero");
+        Ok(Self { alphabet, length })
+    }
+
+    #[must_use]
+    pub fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        (0..self.length)
+            .map(|_| self.alphabet[rng.gen_range(0..self.alphabet.len())])
+            .collect()
+    }
+
+    #[must_use]
+    pub fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.length
+    }
+}
+
+impl Default for AliasGenerator {
+    fn default() -> Self {
+        Self { alphabet: BASE62.chars().collect(), length: DEFAULT_LENGTH }
+    }
+}
+
+/// An alias rejected by the [`AliasPolicy`].
+#[derive(Debug, thiserror::Error)]
+pub enum AliasError {
+    #[error("Alias must be between {0} and {1} characters long")]
+    Length(usize, usize),
+    #[error("Alias contains the disallowed character {0:?}")]
+    Charset(char),
+    #[error("Alias {0:?} is reserved")]
+    Reserved(String),
+    #[error("Alias contains a blocked word")]
+    Blocked,
+}
+
+impl Violation for AliasError {
+    const FIELD: &'static str = "id";
+
+    fn rule(&self) -> &'static str {
+        match self {
+            Self::Length(..) => "length",
+            Self::Charset(_) => "charset",
+            Self::Reserved(_) => "reserved",
+            Self::Blocked => "blocklist",
+        }
+    }
+}
+
+/// How aliases differing only in case are treated.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum CaseFolding {
+    /// Aliases are case-sensitive.
+    #[default]
+    Preserve,
+    /// Aliases are lowercased when created and looked up.
+    Lower,
+}
+
+impl FromStr for CaseFolding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "lower" => Ok(Self::Lower),
+            _ => bail!("Unknown case folding {s:?}, expected preserve or lower"),
+        }
+    }
+}
+
+/// Rules custom and generated aliases have to satisfy.
+#[derive(Clone, Debug)]
+pub struct AliasPolicy {
+    charset: HashSet<char>,
+    min_length: usize,
+    max_length: usize,
+    /// Lowercased words that cannot be used as alias.
+    reserved: HashSet<String>,
+    case: CaseFolding,
+    /// Lowercased words that must not appear anywhere in an alias.
+    blocklist: Vec<String>,
+}
+
+impl AliasPolicy {
+    pub fn new(charset: &str, min_length: usize, max_length: usize) -> anyhow::Result<Self> {
+        ensure!(!charset.is_empty(), "Alias charset must not be empty");
+        ensure!(
+            !charset.contains('+'),
+            "Alias charset must not contain '+', it marks preview URLs"
+        );
+        ensure!(min_length > 0, "Minimum alias length must be greater than zero");
+        ensure!(min_length <= max_length, "Minimum alias length exceeds the maximum");
+        Ok(Self {
+            charset: charset.chars().collect(),
+            min_length,
+            max_length,
+            reserved: HashSet::new(),
+            case: CaseFolding::default(),
+            blocklist: Vec::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn with_reserved<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.reserved.extend(words.into_iter().map(|word| word.as_ref().to_lowercase()));
+        self
+    }
+
+    #[must_use]
+    pub fn with_case_folding(mut self, case: CaseFolding) -> Self {
+        self.case = case;
+        self
+    }
+
+    #[must_use]
+    pub fn with_blocklist<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let words = words.into_iter().map(|word| word.as_ref().trim().to_lowercase());
+        self.blocklist.extend(words.filter(|word| !word.is_empty()));
+        self
+    }
+
+    /// Applies the case folding, use it on every alias taken from a request.
+    #[must_use]
+    pub fn fold(&self, alias: &str) -> String {
+        match self.case {
+            CaseFolding::Preserve => alias.to_string(),
+            CaseFolding::Lower => alias.to_lowercase(),
+        }
+    }
+
+    /// Folds and validates `alias`, returning the form to store.
+    pub fn normalize(&self, alias: &str) -> Result<String, AliasError> {
+        let alias = self.fold(alias);
+        let length = alias.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(AliasError::Length(self.min_length, self.max_length));
+        }
+        if let Some(c) = alias.chars().find(|c| !self.charset.contains(c)) {
+            return Err(AliasError::Charset(c));
+        }
+        let lowercase = alias.to_lowercase();
+        if self.reserved.contains(&lowercase) {
+            return Err(AliasError::Reserved(alias));
+        }
+        if self.blocklist.iter().any(|word| lowercase.contains(word.as_str())) {
+            return Err(AliasError::Blocked);
+        }
+        Ok(alias)
+    }
+
+    /// Ensures aliases of `generator` can satisfy the charset and length rules.
+    pub fn check_generator(&self, generator: &AliasGenerator) -> anyhow::Result<()> {
+        for c in generator.alphabet() {
+            let folded = self.fold(&c.to_string());
+            ensure!(
+                folded.chars().all(|c| self.charset.contains(&c)),
+                "Alias alphabet character {c:?} is not allowed"
+            );
+        }
+        ensure!(
+            (self.min_length..=self.max_length).contains(&generator.length()),
+            "Alias length {} is outside {}..={}",
+            generator.length(),
+            self.min_length,
+            self.max_length
+        );
+        Ok(())
+    }
+}
+
+impl Default for AliasPolicy {
+    fn default() -> Self {
+        Self {
+            charset: DEFAULT_CHARSET.chars().collect(),
+            min_length: DEFAULT_MIN_LENGTH,
+            max_length: DEFAULT_MAX_LENGTH,
+            reserved: HashSet::new(),
+            case: CaseFolding::default(),
+            blocklist: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_aliases_within_policy() {
+        let policy = AliasPolicy::default();
+        assert_eq!(policy.normalize("My-Link_1").unwrap(), "My-Link_1");
+    }
+
+    #[test]
+    fn rejects_rule_violations() {
+        let policy = AliasPolicy::new("abc", 2, 4)
+            .unwrap()
+            .with_reserved(["ab"])
+            .with_blocklist(["cc"]);
+        let rule = |alias| policy.normalize(alias).unwrap_err().rule();
+        assert_eq!(rule("a"), "length");
+        assert_eq!(rule("abcab"), "length");
+        assert_eq!(rule("abd"), "charset");
+        assert_eq!(rule("AB"), "charset");
+        assert_eq!(rule("ab"), "reserved");
+        assert_eq!(rule("acca"), "blocklist");
+        assert_eq!(policy.normalize("abc").unwrap(), "abc");
+    }
+
+    #[test]
+    fn checks_reserved_and_blocked_words_case_insensitively() {
+        let policy = AliasPolicy::default().with_reserved(["Api"]).with_blocklist(["  Spam "]);
+        assert_eq!(policy.normalize("API").unwrap_err().rule(), "reserved");
+        assert_eq!(policy.normalize("mySPAMlink").unwrap_err().rule(), "blocklist");
+    }
+
+    #[test]
+    fn folds_case_before_validating() {
+        let policy = AliasPolicy::new("abc", 1, 8).unwrap().with_case_folding(CaseFolding::Lower);
+        assert_eq!(policy.normalize("AbC").unwrap(), "abc");
+        assert_eq!(policy.fold("ABC"), "abc");
+        assert_eq!(AliasPolicy::default().fold("ABC"), "ABC");
+    }
+
+    #[test]
+    fn counts_length_in_characters() {
+        let policy = AliasPolicy::new("äö", 2, 2).unwrap();
+        assert_eq!(policy.normalize("äö").unwrap(), "äö");
+    }
+}
@@ -0,0 +1,150 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use actix_web::HttpRequest;
+use actix_web::http::header::{self, HeaderName, X_FORWARDED_FOR};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::model::Click;
+use crate::store::LinkStore;
+
+#[derive(Clone, Debug)]
+pub struct RecorderConfig {
+    /// Number of clicks buffered before new clicks are dropped.
+    pub capacity: usize,
+    /// Maximum number of clicks written in one INSERT.
+    pub batch_size: usize,
+    /// Header carrying the client's country code, as set by a CDN or geo-IP proxy in front of us.
+    pub country_header: Option<HeaderName>,
+    pub trusted_proxies: TrustedProxies,
+}
+
+/// Reverse proxies trusted to report the client address in `X-Forwarded-For`.
+///
+/// The header is ignored for requests from other peers, clients could otherwise pick any address.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Arc<[IpAddr]>);
+
+impl TrustedProxies {
+    #[must_use]
+    pub fn new(addresses: impl IntoIterator<Item = IpAddr>) -> Self {
+        Self(addresses.into_iter().collect())
+    }
+
+    /// Address of the client that sent `request`, skipping trusted proxies in `X-Forwarded-For`
+    /// from the nearest hop on.
+    #[must_use]
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client = request.peer_addr()?.ip();
+        if !self.0.contains(&client) {
+            return Some(client);
+        }
+        let forwarded = request
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok());
+        let hops: Vec<_> = forwarded.flat_map(|value| value.split(',')).map(str::trim).collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(address) = hop.parse() else {
+                break;
+            };
+            client = address;
+            if !self.0.contains(&client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Records clicks in the background so redirects do not wait for the INSERT.
+#[derive(Clone)]
+pub struct ClickRecorder {
+    sender: mpsc::Sender<Click>,
+    country_header: Option<HeaderName>,
+    trusted_proxies: TrustedProxies,
+}
+
+impl ClickRecorder {
+    /// Spawns the task writing buffered clicks to the store.
+    #[must_use]
+    pub fn spawn(store: Arc<dyn LinkStore>, config: RecorderConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.capacity);
+        tokio::spawn(write_clicks(store, receiver, config.batch_size));
+        Self {
+            sender,
+            country_header: config.country_header,
+            trusted_proxies: config.trusted_proxies,
+        }
+    }
+
+    /// Queues the click of `link_id` made by `request`, dropping it if the buffer is full.
+    pub fn record(&self, link_id: &str, request: &HttpRequest) {
+        let click = Click {
+            link_id: link_id.to_string(),
+            clicked_at: OffsetDateTime::now_utc(),
+            referer: header_value(request, &header::REFERER),
+            user_agent: header_value(request, &header::USER_AGENT),
+            client_ip: self.trusted_proxies.client_ip(request),
+            accept_language: header_value(request, &header::ACCEPT_LANGUAGE),
+            country: self.country_header.as_ref().and_then(|name| header_value(request, name)),
+        };
+        if let Err(err) = self.sender.try_send(click) {
+            tracing::warn!("Dropping click of {link_id}: {err}");
+        }
+    }
+}
+
+async fn write_clicks(
+    store: Arc<dyn LinkStore>,
+    mut receiver: mpsc::Receiver<Click>,
+    batch_size: usize,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    while receiver.recv_many(&mut batch, batch_size).await > 0 {
+        if let Err(err) = store.insert_clicks(&batch).await {
+            tracing::warn!(error = ?err, "Dropping {} clicks", batch.len());
+        }
+        batch.clear();
+    }
+}
+
+fn header_value(request: &HttpRequest, name: &HeaderName) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn request(peer: [u8; 4], forwarded_for: &str) -> HttpRequest {
+        TestRequest::default()
+            .peer_addr((peer, 4711).into())
+            .insert_header((X_FORWARDED_FOR, forwarded_for))
+            .to_http_request()
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peers() {
+        let proxies = TrustedProxies::default();
+        let client = proxies.client_ip(&request([192, 0, 2, 1], "198.51.100.7"));
+        assert_eq!(client, Some(IpAddr::from([192, 0, 2, 1])));
+    }
+
+    #[test]
+    fn takes_the_nearest_untrusted_hop_from_trusted_proxies() {
+        let proxies =
+            TrustedProxies::new([IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2])]);
+        let request = request([10, 0, 0, 1], "203.0.113.9, 198.51.100.7, 10.0.0.2");
+        assert_eq!(proxies.client_ip(&request), Some(IpAddr::from([198, 51, 100, 7])));
+        let request = self::request([10, 0, 0, 1], "garbage");
+        assert_eq!(proxies.client_ip(&request), Some(IpAddr::from([10, 0, 0, 1])));
+    }
+}
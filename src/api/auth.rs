@@ -0,0 +1,55 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpRequest, web};
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::model::ApiKey;
+use crate::state::State;
+
+const KEY_PREFIX: &str = "us_";
+const KEY_LENGTH: usize = 40;
+
+/// Generates a new random API key, only its hash is stored.
+#[must_use]
+pub fn generate_api_key() -> String {
+    let random = Alphanumeric.sample_string(&mut rand::thread_rng(), KEY_LENGTH);
+    format!("{KEY_PREFIX}{random}")
+}
+
+#[must_use]
+pub fn hash_api_key(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Extractor authenticating the request by its `Authorization: Bearer` API key.
+#[derive(Clone, Debug)]
+pub struct Authenticated(pub ApiKey);
+
+impl FromRequest for Authenticated {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = request.app_data::<web::Data<State>>().cloned();
+        let key = bearer_token(request);
+        Box::pin(async move {
+            let state = state.ok_or_else(|| Error::Internal("Missing state".to_string()))?;
+            let key = key.ok_or_else(|| Error::Unauthorized("Missing API key".to_string()))?;
+            match state.store().find_api_key(&hash_api_key(&key)).await? {
+                Some(api_key) => Ok(Self(api_key)),
+                None => Err(Error::Unauthorized("Invalid API key".to_string())),
+            }
+        })
+    }
+}
+
+fn bearer_token(request: &HttpRequest) -> Option<String> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim().to_string())
+}
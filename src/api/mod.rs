@@ -1,34 +1,136 @@
+pub mod auth;
+mod pages;
+
 use std::net::TcpListener;
 use std::time::Duration;
 
+use actix_web::cookie::{Cookie, SameSite};
 use actix_web::dev::Server;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, ContentType, Header};
 use actix_web::middleware::{Compress, Logger, NormalizePath};
-use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web, http::header};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use self::auth::Authenticated;
+use crate::destination::{self, QueryForwarding};
+use crate::error::Error;
+use crate::model::{
+    self, ApiKey, ClickBucket, ClickDimension, ClickValue, Granularity, LinkCursor, LinkDetails,
+    LinkFilter, LinkRevision, LinkSort, LinkSummary, RedirectType, Resolved,
+};
+use crate::password::{self, PasswordGuard};
+use crate::state::{NotFoundResponse, State};
+use crate::store::LinkStore;
+
+/// Top-level paths served next to the short links and path segments of the routes in `api_config`,
+/// aliases must not be named like them.
+pub const RESERVED_ALIASES: &[&str] = &[
+    "api",
+    "health",
+    "favicon.ico",
+    "robots.txt",
+    "urls",
+    "delete",
+    "trash",
+    "stats",
+    "history",
+    "restore",
+    "rollback",
+    "info",
+];
 
-use crate::state::State;
-use crate::database;
+/// Query parameter showing the preview page instead of redirecting.
+const PREVIEW_PARAMETER: &str = "preview";
+
+const ROBOTS_TXT: &str = "User-agent: *\nDisallow: /api/\n";
+
+fn root_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/health").route(web::get().to(health)))
+        .service(web::resource("/favicon.ico").to(HttpResponse::NotFound))
+        .service(web::resource("/robots.txt").route(web::get().to(robots_txt)))
+        // Registered after the reserved paths so aliases never shadow them
+        .service(
+            web::resource("/{id}")
+                .route(web::get().to(redirect_url))
+                .route(web::post().to(submit_url)),
+        )
+        .service(
+            web::resource("/{id}/{suffix:.*}")
+                .route(web::get().to(redirect_url_suffix))
+                .route(web::post().to(submit_url_suffix)),
+        );
+}
 
 fn api_config(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/urls").route(web::post().to(create_url)))
-        .service(web::resource("/urls/delete").route(web::delete().to(delete_url)))
-        .service(web::resource("/urls/{id}").route(web::get().to(redirect_url)));
+    cfg.service(
+        web::resource("/urls")
+            .route(web::get().to(list_urls))
+            .route(web::post().to(create_url)),
+    )
+    .service(web::resource("/urls/delete").route(web::delete().to(delete_url)))
+    .service(web::resource("/urls/trash").route(web::get().to(list_trash)))
+    .service(
+        web::resource("/urls/{id}")
+            .route(web::get().to(redirect_url))
+            .route(web::post().to(unlock_url))
+            .route(web::patch().to(update_url)),
+    )
+    .service(web::resource("/urls/{id}/info").route(web::get().to(link_info)))
+    .service(web::resource("/urls/{id}/stats").route(web::get().to(link_stats)))
+    .service(web::resource("/urls/{id}/history").route(web::get().to(link_history)))
+    .service(web::resource("/urls/{id}/restore").route(web::post().to(restore_url)))
+    .service(web::resource("/urls/{id}/rollback/{revision}").route(web::post().to(rollback_url)))
+    // Registered last so the routes above take precedence over forwarded paths
+    .service(
+        web::resource("/urls/{id}/{suffix:.*}")
+            .route(web::get().to(redirect_url_suffix))
+            .route(web::post().to(unlock_url_suffix)),
+    );
+}
+
+async fn not_found_handler(_request: HttpRequest) -> Result<HttpResponse, Error> {
+    Err(Error::NotFound("Not found".to_string()))
+}
+
+fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .error_handler(|err, _request| Error::BadRequest(err.to_string()).into())
+}
+
+fn path_config() -> web::PathConfig {
+    web::PathConfig::default()
+        .error_handler(|err, _request| Error::BadRequest(err.to_string()).into())
 }
 
-async fn not_found_handler(_request: HttpRequest) -> HttpResponse {
-    HttpResponse::NotFound().json(serde_json::json!({ "error": "Not found" }))
+fn form_config() -> web::FormConfig {
+    web::FormConfig::default()
+        .error_handler(|err, _request| Error::BadRequest(err.to_string()).into())
+}
+
+fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|err, _request| Error::BadRequest(err.to_string()).into())
 }
 
 pub fn listen(listener: TcpListener, state: State) -> std::io::Result<Server> {
     let state = web::Data::new(state);
     let create_app = move || {
-        let app = App::new().app_data(state.clone());
-        app
+        App::new()
+            .app_data(state.clone())
+            .app_data(json_config())
+            .app_data(path_config())
+            .app_data(query_config())
+            .app_data(form_config())
             .wrap(tracing_actix_web::TracingLogger::default())
             .wrap(Logger::new(r#"%a "%r" %s %b (%{Content-Length}i %{Content-Type}i) "%{Referer}i" "%{User-Agent}i" %T"#))
             .wrap(Compress::default())
             .wrap(NormalizePath::trim())
             .service(web::scope("/api").configure(api_config))
+            .configure(root_config)
             .default_service(web::route().to(not_found_handler))
     };
     let server = HttpServer::new(create_app)
@@ -39,80 +141,947 @@ pub fn listen(listener: TcpListener, state: State) -> std::io::Result<Server> {
 }
 
 /* I'm writing the structs & handlers here to save time & for your reading convenience. */
+/// Maximum number of generated aliases tried before giving up on collisions.
+const MAX_GENERATE_ATTEMPTS: usize = 5;
+/// Number of entries in each top list of the link statistics.
+const STATS_TOP_LIMIT: i64 = 10;
+/// Number of links per page when the client omits `limit`.
+const LIST_DEFAULT_LIMIT: i64 = 50;
+const LIST_MAX_LIMIT: i64 = 500;
+/// Period covered by the link statistics when the client omits `from`.
+const STATS_DEFAULT_PERIOD: time::Duration = time::Duration::days(30);
+
 #[derive(Deserialize, Serialize)]
 struct Link {
     id: String,
-    url: String
+    url: String,
+    #[serde(
+        default,
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    expires_at: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_clicks: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_type: Option<RedirectType>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    forward_path: bool,
+    #[serde(default, skip_serializing_if = "QueryForwarding::is_off")]
+    forward_query: QueryForwarding,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    password_protected: bool,
+}
+#[derive(Deserialize)]
+struct CreateLink {
+    id: Option<String>,
+    url: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    ttl_seconds: Option<u32>,
+    max_clicks: Option<i32>,
+    redirect_type: Option<RedirectType>,
+    #[serde(default)]
+    forward_path: bool,
+    #[serde(default)]
+    forward_query: QueryForwarding,
+    password: Option<String>,
+}
+#[derive(Deserialize)]
+struct UpdateLink {
+    url: Option<String>,
+    #[serde(default, deserialize_with = "present_rfc3339")]
+    expires_at: Option<Option<OffsetDateTime>>,
+    ttl_seconds: Option<u32>,
+    #[serde(default, deserialize_with = "present")]
+    max_clicks: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "present")]
+    redirect_type: Option<Option<RedirectType>>,
+    forward_path: Option<bool>,
+    forward_query: Option<QueryForwarding>,
+    #[serde(default, deserialize_with = "present")]
+    password: Option<Option<String>>,
 }
 #[derive(Deserialize)]
 struct LinkId {
-    id: String
+    id: String,
+}
+#[derive(Deserialize)]
+struct LinkSuffix {
+    id: String,
+    suffix: String,
+}
+#[derive(Deserialize)]
+struct Unlock {
+    password: String,
+}
+#[derive(Deserialize)]
+struct RevisionId {
+    id: String,
+    revision: i32,
+}
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+#[derive(Deserialize)]
+struct ListQuery {
+    cursor: Option<String>,
+    limit: Option<i64>,
+    host: Option<String>,
+    owner: Option<i64>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_after: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_before: Option<OffsetDateTime>,
+    prefix: Option<String>,
+    #[serde(default)]
+    sort: LinkSort,
+    #[serde(default)]
+    order: SortOrder,
+}
+#[derive(Serialize)]
+struct LinkPage {
+    links: Vec<LinkSummary>,
+    next_cursor: Option<String>,
+}
+#[derive(Serialize)]
+struct LinkPreview {
+    #[serde(flatten)]
+    link: LinkDetails,
+    /// Where the request would have been redirected, including forwarded path and query.
+    destination: String,
+    status: &'static str,
+    blocked: bool,
+}
+#[derive(Serialize)]
+struct LinkHistory {
+    id: String,
+    revisions: Vec<LinkRevision>,
+}
+#[derive(Deserialize)]
+struct StatsQuery {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    to: Option<OffsetDateTime>,
+    #[serde(default)]
+    granularity: Granularity,
+}
+#[derive(Serialize)]
+struct LinkStats {
+    id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    from: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    to: OffsetDateTime,
+    total_clicks: i64,
+    unique_visitors: i64,
+    series: Vec<ClickBucket>,
+    top_referers: Vec<ClickValue>,
+    top_user_agents: Vec<ClickValue>,
+    top_countries: Vec<ClickValue>,
 }
 
-// Create short aliases for URLs
-async fn create_url(state: web::Data<State>, body: web::Json<Link>) -> HttpResponse {
-    let client = match state.database_client().await {
-        Ok(client) => client,
-        Err(err) => {
-            eprintln!("Error connecting to database: {:?}", err);
-            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error connecting to database" }))
+// Resolve `expires_at` or `ttl_seconds` into the absolute expiry time
+fn expiry(
+    expires_at: Option<OffsetDateTime>,
+    ttl_seconds: Option<u32>,
+) -> Result<Option<OffsetDateTime>, Error> {
+    let expires_at = match (expires_at, ttl_seconds) {
+        (Some(_), Some(_)) => {
+            return Err(Error::BadRequest(
+                "Use either expires_at or ttl_seconds, not both".to_string(),
+            ));
         }
+        (Some(expires_at), None) => expires_at,
+        (None, Some(ttl)) => OffsetDateTime::now_utc() + time::Duration::seconds(ttl.into()),
+        (None, None) => return Ok(None),
     };
+    if expires_at <= OffsetDateTime::now_utc() {
+        return Err(Error::BadRequest("Expiry must be in the future".to_string()));
+    }
+    Ok(Some(expires_at))
+}
 
-    match database::create_link(&client, &body.id, &body.url).await {
-        Ok(_) => {
-            let response = Link {
-                id: format!("{}", body.id),
-                url: format!("{}", body.url)
-            };
+fn validate_max_clicks(max_clicks: Option<i32>) -> Result<Option<i32>, Error> {
+    match max_clicks {
+        Some(max_clicks) if max_clicks < 1 => {
+            Err(Error::BadRequest("max_clicks must be at least 1".to_string()))
+        }
+        max_clicks => Ok(max_clicks),
+    }
+}
 
-            HttpResponse::Ok().json(response)
-        },
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error shortening URL" }))
+// Deserialize a present field into `Some`, so `null` can be told apart from a missing field
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn present_rfc3339<'de, D>(deserializer: D) -> Result<Option<Option<OffsetDateTime>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    time::serde::rfc3339::option::deserialize(deserializer).map(Some)
+}
+
+fn etag(version: i64) -> header::ETag {
+    header::ETag(header::EntityTag::new_strong(version.to_string()))
+}
+
+// Link versions accepted by the `If-Match` header, `None` if any version is accepted
+fn if_match_versions(request: &HttpRequest) -> Result<Option<Vec<i64>>, Error> {
+    if !request.headers().contains_key(header::IF_MATCH) {
+        return Ok(None);
+    }
+    match header::IfMatch::parse(request) {
+        Ok(header::IfMatch::Any) => Ok(None),
+        Ok(header::IfMatch::Items(tags)) => {
+            let strong = tags.iter().filter(|tag| !tag.weak);
+            Ok(Some(strong.filter_map(|tag| tag.tag().parse().ok()).collect()))
+        }
+        Err(_) => Err(Error::BadRequest("Invalid If-Match header".to_string())),
+    }
+}
+
+// Create short aliases for URLs, generating one when the client omits `id`
+async fn create_url(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    body: web::Json<CreateLink>,
+) -> Result<HttpResponse, Error> {
+    let expires_at = expiry(body.expires_at, body.ttl_seconds)?;
+    let max_clicks = validate_max_clicks(body.max_clicks)?;
+    let quarantine = state.alias_quarantine();
+
+    let CreateLink { id, url, redirect_type, forward_path, forward_query, password, .. } =
+        body.into_inner();
+    let url = check_destination(&state, &url)?;
+    let password_hash = match password {
+        Some(password) => Some(hash_password(password).await?),
+        None => None,
+    };
+    let id = id.map(|id| state.alias_policy().normalize(&id)).transpose()?;
+    let attempts = if id.is_some() { 1 } else { MAX_GENERATE_ATTEMPTS };
+    for _ in 0..attempts {
+        let id = match &id {
+            Some(id) => id.clone(),
+            None => generate_alias(&state)?,
+        };
+        let link = model::NewLink {
+            id: &id,
+            url: &url,
+            expires_at,
+            max_clicks,
+            owner: Some(key.id),
+            redirect_type,
+            forward_path,
+            forward_query,
+            password_hash: password_hash.as_deref(),
+        };
+        match state.store().create_link(&link, quarantine).await {
+            Ok(()) => {
+                invalidate_cache(&state, &id);
+                let password_protected = password_hash.is_some();
+                let link = Link {
+                    id,
+                    url,
+                    expires_at,
+                    max_clicks,
+                    redirect_type,
+                    forward_path,
+                    forward_query,
+                    password_protected,
+                };
+                return Ok(HttpResponse::Ok().insert_header(etag(1)).json(link));
+            }
+            Err(Error::Conflict(_)) if attempts > 1 => {
+                tracing::debug!("Generated alias {id} already exists, retrying");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(Error::Internal(format!("No unique alias found after {attempts} attempts")))
+}
+
+// Drop the cached redirect of a link after modifying it
+fn invalidate_cache(state: &State, id: &str) {
+    if let Some(cache) = state.redirect_cache() {
+        cache.invalidate(id);
+    }
+}
+
+// Normalize a destination URL and ensure it is not blocked
+fn check_destination(state: &State, url: &str) -> Result<String, Error> {
+    let url = state.destination_policy().normalize(url)?;
+    state.blocklist().check(&url)?;
+    Ok(url)
+}
+
+// Generate an alias satisfying the alias policy, skipping reserved and blocked words
+fn generate_alias(state: &State) -> Result<String, Error> {
+    for _ in 0..MAX_GENERATE_ATTEMPTS {
+        let generated = state.alias_generator().generate();
+        match state.alias_policy().normalize(&generated) {
+            Ok(id) => return Ok(id),
+            Err(err) => tracing::debug!("Discarding generated alias {generated}: {err}"),
+        }
+    }
+    Err(Error::Internal(format!(
+        "No acceptable alias generated after {MAX_GENERATE_ATTEMPTS} attempts"
+    )))
+}
+
+// Move short aliases to the trash
+async fn delete_url(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    body: web::Json<LinkId>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&body.id);
+
+    authorize_owner(state.store(), id, &key).await?;
+    state.store().delete_link(id).await?;
+    invalidate_cache(&state, id);
+    Ok(HttpResponse::Ok()
+        .json(serde_json::json!({ "status": "success", "message": "Link deleted" })))
+}
+
+// Take a short alias out of the trash
+async fn restore_url(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    params: web::Path<LinkId>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&params.id);
+
+    match state.store().get_link_owner(id).await? {
+        Some(link) if link.deleted => check_owner(id, link.owner, &key)?,
+        _ => return Err(Error::NotFound(format!("Alias {id} is not in the trash"))),
     }
+    let Some(version) = state.store().restore_link(id).await? else {
+        return Err(Error::NotFound(format!("Alias {id} is not in the trash")));
+    };
+    invalidate_cache(&state, id);
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(version))
+        .json(serde_json::json!({ "status": "success", "message": "Link restored" })))
+}
+
+// List links page by page, `next_cursor` continues after the last link of the page
+async fn list_urls(
+    state: web::Data<State>,
+    _key: Authenticated,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, Error> {
+    list_page(&state, &query, false).await
+}
+
+// List deleted links that can still be restored
+async fn list_trash(
+    state: web::Data<State>,
+    _key: Authenticated,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, Error> {
+    list_page(&state, &query, true).await
 }
 
-// Delete short aliases for URLs
-async fn delete_url(state: web::Data<State>, body: web::Json<LinkId>) -> HttpResponse {
-    let client = match state.database_client().await {
-        Ok(client) => client,
-        Err(err) => {
-            eprintln!("Error connecting to database: {:?}", err);
-            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error connecting to database" }))
+async fn list_page(state: &State, query: &ListQuery, deleted: bool) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(LIST_DEFAULT_LIMIT);
+    if !(1..=LIST_MAX_LIMIT).contains(&limit) {
+        return Err(Error::BadRequest(format!("limit must be between 1 and {LIST_MAX_LIMIT}")));
+    }
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+    match (&after, query.sort) {
+        (None, _)
+        | (Some(LinkCursor::CreatedAt(..)), LinkSort::CreatedAt)
+        | (Some(LinkCursor::Clicks(..)), LinkSort::Clicks) => {}
+        _ => {
+            return Err(Error::Unprocessable {
+                field: "cursor",
+                rule: "sort",
+                detail: "Cursor belongs to a different sort".to_string(),
+            });
         }
+    }
+    let prefix = query.prefix.as_deref().map(|prefix| state.alias_policy().fold(prefix));
+    let filter = LinkFilter {
+        host: query.host.as_deref(),
+        owner: query.owner,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        prefix: prefix.as_deref(),
+        deleted,
+    };
+    let descending = matches!(query.order, SortOrder::Desc);
+
+    let mut links = state
+        .store()
+        .list_links(&filter, query.sort, descending, after.as_ref(), limit + 1)
+        .await?;
+    let next_cursor = if links.len() as i64 > limit {
+        links.truncate(limit as usize);
+        links.last().map(|link| encode_cursor(&link.cursor(query.sort))).transpose()?
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(LinkPage { links, next_cursor }))
+}
+
+fn encode_cursor(cursor: &LinkCursor) -> Result<String, Error> {
+    let json = serde_json::to_vec(cursor).map_err(|err| Error::Internal(err.to_string()))?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode_cursor(cursor: &str) -> Result<LinkCursor, Error> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| Error::Unprocessable {
+            field: "cursor",
+            rule: "syntax",
+            detail: "Invalid cursor".to_string(),
+        })
+}
+
+// Change the destination or other attributes of an alias, honoring `If-Match` against its ETag
+async fn update_url(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    params: web::Path<LinkId>,
+    request: HttpRequest,
+    body: web::Json<UpdateLink>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&params.id);
+    let versions = if_match_versions(&request)?;
+    let expires_at = match (body.expires_at, body.ttl_seconds) {
+        (Some(None), None) => Some(None),
+        (expires_at, ttl_seconds) => expiry(expires_at.flatten(), ttl_seconds)?.map(Some),
+    };
+    let max_clicks = match body.max_clicks {
+        Some(max_clicks) => Some(validate_max_clicks(max_clicks)?),
+        None => None,
+    };
+    let url = body.url.as_deref().map(|url| check_destination(&state, url)).transpose()?;
+    let password_hash = match body.password.clone() {
+        Some(Some(password)) => Some(Some(hash_password(password).await?)),
+        Some(None) => Some(None),
+        None => None,
+    };
+    let changes = model::LinkChanges {
+        url: url.as_deref(),
+        expires_at,
+        max_clicks,
+        redirect_type: body.redirect_type,
+        forward_path: body.forward_path,
+        forward_query: body.forward_query,
+        password_hash: password_hash.as_ref().map(Option::as_deref),
     };
+    if changes.is_empty() {
+        return Err(Error::BadRequest("Nothing to update".to_string()));
+    }
+
+    authorize_owner(state.store(), id, &key).await?;
+    let response = apply_update(state.store(), id, &key, &changes, versions.as_deref()).await?;
+    invalidate_cache(&state, id);
+    Ok(response)
+}
+
+// Apply `changes` to an alias already authorized for `key`, recording a revision when the
+// destination changes
+async fn apply_update(
+    store: &dyn LinkStore,
+    id: &str,
+    key: &ApiKey,
+    changes: &model::LinkChanges<'_>,
+    versions: Option<&[i64]>,
+) -> Result<HttpResponse, Error> {
+    let Some(link) = store.update_link(id, changes, versions, Some(key.id)).await? else {
+        return Err(Error::PreconditionFailed(format!("Alias {id} was modified concurrently")));
+    };
+    let response = Link {
+        id: id.to_string(),
+        url: link.url,
+        expires_at: link.expires_at,
+        max_clicks: link.max_clicks,
+        redirect_type: link.redirect_type,
+        forward_path: link.forward_path,
+        forward_query: link.forward_query,
+        password_protected: link.password_protected,
+    };
+    Ok(HttpResponse::Ok().insert_header(etag(link.version)).json(response))
+}
+
+// Show the current attributes of an alias with the ETag to send in `If-Match` when updating it
+async fn link_info(
+    state: web::Data<State>,
+    _key: Authenticated,
+    params: web::Path<LinkId>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&params.id);
+
+    let Some(link) = state.store().get_link_summary(id).await? else {
+        return Err(Error::NotFound(format!("Unknown alias {id}")));
+    };
+    Ok(HttpResponse::Ok().insert_header(etag(link.version)).json(link))
+}
+
+// List the destinations an alias pointed to, newest first
+async fn link_history(
+    state: web::Data<State>,
+    _key: Authenticated,
+    params: web::Path<LinkId>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&params.id);
 
-    match database::delete_link(&client, &body.id).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "success", "message": "Link deleted" })),
-        Err(err) => {
-            eprintln!("Error deleting Link: {:?}", err);
-            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error deleting Link" }))
+    if !state.store().link_exists(id).await? {
+        return Err(Error::NotFound(format!("Unknown alias {id}")));
+    }
+    let revisions = state.store().list_link_revisions(id).await?;
+    Ok(HttpResponse::Ok().json(LinkHistory { id: id.clone(), revisions }))
+}
+
+// Restore the destination of an earlier revision, recorded as a new revision
+async fn rollback_url(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    params: web::Path<RevisionId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let RevisionId { id, revision } = params.into_inner();
+    let id = state.alias_policy().fold(&id);
+    let versions = if_match_versions(&request)?;
+
+    authorize_owner(state.store(), &id, &key).await?;
+    let Some(url) = state.store().get_link_revision(&id, revision).await? else {
+        return Err(Error::NotFound(format!("Alias {id} has no revision {revision}")));
+    };
+    // The destination policy or blocklist may have changed since the revision was recorded
+    let url = check_destination(&state, &url)?;
+    let changes = model::LinkChanges { url: Some(&url), ..Default::default() };
+    let response = apply_update(state.store(), &id, &key, &changes, versions.as_deref()).await?;
+    invalidate_cache(&state, &id);
+    Ok(response)
+}
+
+// Ensure `key` may modify the alias, links created before authentication may be modified by any key
+async fn authorize_owner(store: &dyn LinkStore, id: &str, key: &ApiKey) -> Result<(), Error> {
+    match store.get_link_owner(id).await? {
+        Some(link) if !link.deleted => check_owner(id, link.owner, key),
+        _ => Err(Error::NotFound(format!("Unknown alias {id}"))),
+    }
+}
+
+fn check_owner(id: &str, owner: Option<i64>, key: &ApiKey) -> Result<(), Error> {
+    match owner {
+        Some(owner) if owner != key.id => {
+            Err(Error::Forbidden(format!("Alias {id} belongs to another API key")))
         }
+        _ => Ok(()),
     }
 }
 
 // Redirect all requests for an alias to the full URL
-async fn redirect_url(state: web::Data<State>, params: web::Path<LinkId>) -> HttpResponse {
-    let id = &params.id;
-
-    let client = match state.database_client().await {
-        Ok(client) => client,
-        Err(err) => {
-            eprintln!("Error connecting to database: {:?}", err);
-            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error connecting to database" }))
+async fn redirect_url(
+    state: web::Data<State>,
+    params: web::Path<LinkId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    redirect(&state, &params.id, None, &request).await
+}
+
+// Redirect to a destination of a link forwarding paths, appending the rest of the request path
+async fn redirect_url_suffix(
+    state: web::Data<State>,
+    params: web::Path<LinkSuffix>,
+    request: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    redirect(&state, &params.id, Some(&params.suffix), &request).await
+}
+
+async fn redirect(
+    state: &State,
+    id: &str,
+    suffix: Option<&str>,
+    request: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let query = request.query_string();
+    match id.strip_suffix('+') {
+        Some(id) => return preview(state, id, suffix, query, request).await,
+        None if wants_preview(query) => return preview(state, id, suffix, query, request).await,
+        None => {}
+    }
+    let id = &state.alias_policy().fold(id);
+    let target = match state.redirect_cache().and_then(|cache| cache.get(id)) {
+        Some(Some(target)) => target,
+        Some(None) => return unknown_alias(state, id),
+        None => {
+            let unlocked = is_unlocked(state, id, request).await?;
+            match state.store().get_link(id, unlocked).await? {
+                Some(Resolved::Active(target)) => {
+                    if let Some(cache) = state.redirect_cache() {
+                        cache.insert(id, &target);
+                    }
+                    target
+                }
+                Some(Resolved::Locked) => return Ok(password_form(StatusCode::OK, id, None)),
+                Some(Resolved::Expired) => {
+                    return Err(Error::Gone(format!("Alias {id} has expired")));
+                }
+                Some(Resolved::Exhausted) => {
+                    return Err(Error::Gone(format!("Alias {id} has no clicks left")));
+                }
+                None => {
+                    if let Some(cache) = state.redirect_cache() {
+                        cache.insert_missing(id);
+                    }
+                    return unknown_alias(state, id);
+                }
+            }
         }
     };
+    if suffix.is_some() && !target.forward_path {
+        return Err(Error::NotFound(format!("Alias {id} does not forward paths")));
+    }
+    let url = destination::forward(&target.url, suffix, query, target.forward_query)
+        .map_err(|err| Error::BadRequest(err.to_string()))?;
+    if let Err(err) = state.blocklist().check(&url) {
+        tracing::info!("Not redirecting alias {id}: {err}");
+        return Ok(HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(pages::blocked(&url)));
+    }
+    if let Some(recorder) = state.click_recorder() {
+        recorder.record(id, request);
+    }
+    let redirect_type = target.redirect_type.unwrap_or(state.redirect_type());
+    let cache_control = redirect_cache_control(
+        state,
+        redirect_type,
+        target.expires_at,
+        target.limited,
+        target.password_protected,
+    );
+    let status =
+        StatusCode::from_u16(redirect_type.code()).expect("redirect types are valid status codes");
+    Ok(HttpResponse::build(status)
+        .append_header((header::LOCATION, url))
+        .insert_header(cache_control)
+        .finish())
+}
+
+// Show where a link goes instead of following it, as HTML or as JSON when the client accepts it
+async fn preview(
+    state: &State,
+    id: &str,
+    suffix: Option<&str>,
+    query: &str,
+    request: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(id);
 
-    match database::get_link(&client, &id).await {
-        Ok(url) if !url.is_empty() => {
-            HttpResponse::Found().append_header((header::LOCATION, url)).finish()
-        },
-        Ok(_) => {
-            HttpResponse::Ok().into()
-        },
-        Err(err) => {
-            eprintln!("Error redirecting to full URL: {:?}", err);
-            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Error redirecting to full URL" }))
+    let Some(link) = state.store().get_link_details(id).await? else {
+        return unknown_alias(state, id);
+    };
+    if suffix.is_some() && !link.forward_path {
+        return Err(Error::NotFound(format!("Alias {id} does not forward paths")));
+    }
+    if link.password_protected && !is_unlocked(state, id, request).await? {
+        if prefers_json(request) {
+            return Err(Error::Forbidden(format!("Alias {id} is password protected")));
         }
+        return Ok(password_form(StatusCode::OK, id, None));
+    }
+    let query = without_preview(query);
+    let destination = destination::forward(&link.url, suffix, &query, link.forward_query)
+        .map_err(|err| Error::BadRequest(err.to_string()))?;
+    let status = if link
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc())
+    {
+        "expired"
+    } else if link.remaining_clicks == Some(0) {
+        "exhausted"
+    } else {
+        "active"
+    };
+    let blocked = state.blocklist().check(&destination).is_err();
+    let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+    if prefers_json(request) {
+        let preview = LinkPreview { link, destination, status, blocked };
+        return Ok(HttpResponse::Ok().insert_header(cache_control).json(preview));
     }
-}
\ No newline at end of file
+    Ok(HttpResponse::Ok()
+        .insert_header(cache_control)
+        .content_type(ContentType::html())
+        .body(pages::preview(&link, &destination, status, blocked)))
+}
+
+// Forward a POST to a short link, or unlock it when it is password protected
+async fn submit_url(
+    state: web::Data<State>,
+    params: web::Path<LinkId>,
+    request: HttpRequest,
+    form: Option<web::Form<Unlock>>,
+) -> Result<HttpResponse, Error> {
+    submit(&state, &params.id, None, &request, form).await
+}
+
+async fn submit_url_suffix(
+    state: web::Data<State>,
+    params: web::Path<LinkSuffix>,
+    request: HttpRequest,
+    form: Option<web::Form<Unlock>>,
+) -> Result<HttpResponse, Error> {
+    submit(&state, &params.id, Some(&params.suffix), &request, form).await
+}
+
+async fn submit(
+    state: &State,
+    id: &str,
+    suffix: Option<&str>,
+    request: &HttpRequest,
+    form: Option<web::Form<Unlock>>,
+) -> Result<HttpResponse, Error> {
+    let alias = state.alias_policy().fold(id.strip_suffix('+').unwrap_or(id));
+    let Some(Some(hash)) = state.store().get_link_password(&alias).await? else {
+        return redirect(state, id, suffix, request).await;
+    };
+    match form {
+        Some(form) => unlock(state, &alias, hash, request, form.into_inner().password).await,
+        // Clients holding an unlock cookie follow the link, others get the password form
+        None => redirect(state, id, suffix, request).await,
+    }
+}
+
+// Unlock a protected link from the password form without forwarding other requests
+async fn unlock_url(
+    state: web::Data<State>,
+    params: web::Path<LinkId>,
+    request: HttpRequest,
+    form: web::Form<Unlock>,
+) -> Result<HttpResponse, Error> {
+    unlock_form(&state, &params.id, &request, form.into_inner().password).await
+}
+
+async fn unlock_url_suffix(
+    state: web::Data<State>,
+    params: web::Path<LinkSuffix>,
+    request: HttpRequest,
+    form: web::Form<Unlock>,
+) -> Result<HttpResponse, Error> {
+    unlock_form(&state, &params.id, &request, form.into_inner().password).await
+}
+
+async fn unlock_form(
+    state: &State,
+    id: &str,
+    request: &HttpRequest,
+    password: String,
+) -> Result<HttpResponse, Error> {
+    let alias = state.alias_policy().fold(id.strip_suffix('+').unwrap_or(id));
+    match state.store().get_link_password(&alias).await? {
+        Some(Some(hash)) => unlock(state, &alias, hash, request, password).await,
+        Some(None) => Ok(see_other(request)),
+        None => unknown_alias(state, &alias),
+    }
+}
+
+// Check the password of a protected link and remember it in a cookie before showing the link again
+async fn unlock(
+    state: &State,
+    id: &str,
+    hash: String,
+    request: &HttpRequest,
+    password: String,
+) -> Result<HttpResponse, Error> {
+    let address = state.trusted_proxies().client_ip(request);
+    let guard = state.password_guard();
+
+    if let Some(retry_after) = guard.retry_after(id, address) {
+        let mut response = password_form(
+            StatusCode::TOO_MANY_REQUESTS,
+            id,
+            Some("Too many failed attempts, try again later."),
+        );
+        let retry_after = header::HeaderValue::from(retry_after.as_secs().max(1));
+        response.headers_mut().insert(header::RETRY_AFTER, retry_after);
+        return Ok(response);
+    }
+
+    let (hash, valid) = web::block(move || {
+        let valid = password::verify_password(&hash, &password);
+        (hash, valid)
+    })
+    .await
+    .map_err(|err| Error::Internal(err.to_string()))?;
+    if !valid {
+        guard.record_failure(id, address);
+        return Ok(password_form(StatusCode::FORBIDDEN, id, Some("Wrong password.")));
+    }
+    let max_age = i64::try_from(guard.cookie_ttl().as_secs()).unwrap_or(i64::MAX);
+    let cookie = Cookie::build(PasswordGuard::cookie_name(id), guard.sign(id, &hash))
+        .path("/")
+        .http_only(true)
+        .secure(request.connection_info().scheme() == "https")
+        .same_site(SameSite::Lax)
+        .max_age(actix_web::cookie::time::Duration::seconds(max_age))
+        .finish();
+    let mut response = see_other(request);
+    response.add_cookie(&cookie).map_err(|err| Error::Internal(err.to_string()))?;
+    Ok(response)
+}
+
+// Whether the request carries a valid unlock cookie for the alias and its current password
+async fn is_unlocked(state: &State, id: &str, request: &HttpRequest) -> Result<bool, Error> {
+    let Some(cookie) = request.cookie(&PasswordGuard::cookie_name(id)) else {
+        return Ok(false);
+    };
+    let Some(Some(hash)) = state.store().get_link_password(id).await? else {
+        return Ok(false);
+    };
+    Ok(state.password_guard().verify(id, &hash, cookie.value()))
+}
+
+fn password_form(status: StatusCode, id: &str, error: Option<&str>) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+        .content_type(ContentType::html())
+        .body(pages::password_form(id, error))
+}
+
+// Send the client back to the requested URL with a GET request
+fn see_other(request: &HttpRequest) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .append_header((header::LOCATION, request.uri().to_string()))
+        .finish()
+}
+
+// Hash a link password on the blocking thread pool, Argon2 is deliberately slow
+async fn hash_password(password: String) -> Result<String, Error> {
+    web::block(move || password::hash_password(&password))
+        .await
+        .map_err(|err| Error::Internal(err.to_string()))?
+}
+
+fn wants_preview(query: &str) -> bool {
+    url::form_urlencoded::parse(query.as_bytes())
+        .any(|(name, value)| name == PREVIEW_PARAMETER && value != "0")
+}
+
+// Remove the preview parameter so it is not forwarded to the destination
+fn without_preview(query: &str) -> String {
+    let parts = query
+        .split('&')
+        .filter(|part| part.split('=').next() != Some(PREVIEW_PARAMETER));
+    parts.collect::<Vec<_>>().join("&")
+}
+
+// Whether the `Accept` header ranks JSON above HTML
+fn prefers_json(request: &HttpRequest) -> bool {
+    let Ok(accept) = header::Accept::parse(request) else {
+        return false;
+    };
+    accept
+        .ranked()
+        .iter()
+        .find_map(|mime| match mime.essence_str() {
+            "text/html" | "text/*" | "*/*" => Some(false),
+            "application/json" | "application/*" => Some(true),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+// Let clients cache permanent redirects until the link expires, temporary and click-limited ones
+// must reach the server on every click and password-protected ones must not reach shared caches
+fn redirect_cache_control(
+    state: &State,
+    redirect_type: RedirectType,
+    expires_at: Option<OffsetDateTime>,
+    limited: bool,
+    password_protected: bool,
+) -> header::CacheControl {
+    if password_protected {
+        return header::CacheControl(vec![
+            header::CacheDirective::Private,
+            header::CacheDirective::NoStore,
+        ]);
+    }
+    if !redirect_type.is_permanent() || limited {
+        return header::CacheControl(vec![header::CacheDirective::NoStore]);
+    }
+    let mut max_age = state.redirect_max_age().as_secs();
+    if let Some(expires_at) = expires_at {
+        let remaining = (expires_at - OffsetDateTime::now_utc()).whole_seconds();
+        max_age = max_age.min(u64::try_from(remaining).unwrap_or_default());
+    }
+    header::CacheControl(vec![
+        header::CacheDirective::Public,
+        header::CacheDirective::MaxAge(u32::try_from(max_age).unwrap_or(u32::MAX)),
+    ])
+}
+
+// Report whether the database can be reached, for load balancers and orchestrators
+async fn health(state: web::Data<State>) -> Result<HttpResponse, Error> {
+    state.store().ping().await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "ok" })))
+}
+
+async fn robots_txt() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::plaintext()).body(ROBOTS_TXT)
+}
+
+// Answer a request for an alias that does not exist
+fn unknown_alias(state: &State, id: &str) -> Result<HttpResponse, Error> {
+    match state.not_found() {
+        NotFoundResponse::Problem => Err(Error::NotFound(format!("Unknown alias {id}"))),
+        NotFoundResponse::Redirect(url) => {
+            Ok(HttpResponse::Found().append_header((header::LOCATION, url.as_str())).finish())
+        }
+        NotFoundResponse::Page(html) => Ok(HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(html.to_string())),
+    }
+}
+
+// Aggregate the recorded clicks of an alias owned by `key`
+async fn link_stats(
+    state: web::Data<State>,
+    Authenticated(key): Authenticated,
+    params: web::Path<LinkId>,
+    query: web::Query<StatsQuery>,
+) -> Result<HttpResponse, Error> {
+    let id = &state.alias_policy().fold(&params.id);
+    let to = query.to.unwrap_or_else(OffsetDateTime::now_utc);
+    let from = query.from.unwrap_or(to - STATS_DEFAULT_PERIOD);
+    if from >= to {
+        return Err(Error::BadRequest("from must be before to".to_string()));
+    }
+
+    let store = state.store();
+    authorize_owner(store, id, &key).await?;
+    let total = store.count_clicks(id, from, to).await?;
+    let series = store.click_series(id, from, to, query.granularity).await?;
+    let top = |dimension| store.top_click_values(id, from, to, dimension, STATS_TOP_LIMIT);
+    let stats = LinkStats {
+        id: id.clone(),
+        from,
+        to,
+        total_clicks: total.clicks,
+        unique_visitors: total.unique_visitors,
+        series,
+        top_referers: top(ClickDimension::Referer).await?,
+        top_user_agents: top(ClickDimension::UserAgent).await?,
+        top_countries: top(ClickDimension::Country).await?,
+    };
+    Ok(HttpResponse::Ok().json(stats))
+}
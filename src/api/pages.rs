@@ -0,0 +1,96 @@
+//! HTML pages served to browsers following short links.
+
+use time::format_description::well_known::Rfc3339;
+
+use crate::model::LinkDetails;
+
+// Escape text for use in HTML element content and quoted attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>body {{ font-family: sans-serif; max-width: 40rem; margin: 4rem auto; padding: 0 1rem; }} code {{ word-break: break-all; }}</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}
+</body>
+</html>
+"#,
+        title = escape(title),
+    )
+}
+
+/// Warning shown instead of redirecting to a blocked destination, which is deliberately not linked.
+pub fn blocked(url: &str) -> String {
+    let body = format!(
+        "<p>This short link points to a destination that has been blocked as potentially harmful, \
+         so you were not redirected.</p>\n<p>Destination: <code>{}</code></p>",
+        escape(url)
+    );
+    page("Link blocked", &body)
+}
+
+/// Where a link goes and who created it, shown instead of redirecting.
+pub fn preview(link: &LinkDetails, destination: &str, status: &str, blocked: bool) -> String {
+    let owner = match (&link.owner_name, link.owner) {
+        (Some(name), _) => escape(name),
+        (None, Some(owner)) => format!("API key {owner}"),
+        (None, None) => "Unknown".to_string(),
+    };
+    let created_at = link.created_at.format(&Rfc3339).unwrap_or_default();
+    let expires_at = match link.expires_at {
+        Some(expires_at) => expires_at.format(&Rfc3339).unwrap_or_default(),
+        None => "Never".to_string(),
+    };
+    let destination = if blocked {
+        format!("<code>{}</code> (blocked, links to it are not followed)", escape(destination))
+    } else {
+        format!(
+            r#"<a href="{0}" rel="noopener noreferrer nofollow"><code>{0}</code></a>"#,
+            escape(destination)
+        )
+    };
+    let body = format!(
+        "<dl>\n<dt>Destination</dt><dd>{destination}</dd>\n<dt>Status</dt><dd>{status}</dd>\n\
+         <dt>Created</dt><dd>{created_at}</dd>\n<dt>Expires</dt><dd>{expires_at}</dd>\n\
+         <dt>Owner</dt><dd>{owner}</dd>\n<dt>Clicks</dt><dd>{clicks}</dd>\n</dl>",
+        status = escape(status),
+        clicks = link.clicks,
+    );
+    page(&format!("Short link {}", link.id), &body)
+}
+
+/// Password prompt of a protected link, posting back to the requested URL.
+pub fn password_form(id: &str, error: Option<&str>) -> String {
+    let error = match error {
+        Some(error) => format!("<p><strong>{}</strong></p>\n", escape(error)),
+        None => String::new(),
+    };
+    let body = format!(
+        "<p>This short link is password protected.</p>\n{error}<form method=\"post\">\n\
+         <input type=\"password\" name=\"password\" aria-label=\"Password\" required autofocus>\n\
+         <button type=\"submit\">Continue</button>\n</form>"
+    );
+    page(&format!("Short link {id}"), &body)
+}
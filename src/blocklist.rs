@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, bail, ensure};
+use tokio::task::JoinHandle;
+use url::{Host, Url};
+
+use crate::error::Violation;
+
+/// Names found in hosts files that describe the local machine rather than a blocked domain.
+const HOSTS_LOCAL_NAMES: &[&str] = &[
+    "0.0.0.0",
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+];
+
+/// A destination URL rejected by the [`Blocklist`].
+#[derive(Debug, thiserror::Error)]
+pub enum BlockedError {
+    #[error("URL domain {0:?} is blocked")]
+    Domain(String),
+    #[error("URL matches the blocked prefix {0:?}")]
+    Prefix(String),
+    #[error("URL domain {0:?} is not on the allowlist")]
+    NotAllowed(String),
+}
+
+impl Violation for BlockedError {
+    const FIELD: &'static str = "url";
+
+    fn rule(&self) -> &'static str {
+        match self {
+            Self::Domain(_) | Self::Prefix(_) => "blocklist",
+            Self::NotAllowed(_) => "allowlist",
+        }
+    }
+}
+
+/// Files the [`Blocklist`] is loaded from.
+///
+/// Each line holds a domain, a URL prefix (anything containing `://`) or a hosts file entry, `#`
+/// starts a comment. Domains also match their subdomains.
+#[derive(Clone, Debug, Default)]
+pub struct BlocklistConfig {
+    /// Files with blocked domains and URL prefixes.
+    pub deny: Vec<PathBuf>,
+    /// Files with the only domains links may point to, all domains are allowed when empty.
+    pub allow: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct Rules {
+    denied_domains: HashSet<String>,
+    denied_prefixes: Vec<String>,
+    allowed_domains: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    domains: HashSet<String>,
+    prefixes: Vec<String>,
+}
+
+/// Domain and URL prefix rules destination URLs are checked against, shared by all clones and
+/// replaced as a whole on [`reload`](Self::reload).
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    config: Arc<BlocklistConfig>,
+    rules: Arc<RwLock<Arc<Rules>>>,
+}
+
+impl Blocklist {
+    pub fn load(config: BlocklistConfig) -> anyhow::Result<Self> {
+        let rules = load_rules(&config)?;
+        Ok(Self {
+            config: Arc::new(config),
+            rules: Arc::new(RwLock::new(Arc::new(rules))),
+        })
+    }
+
+    /// Reads the files again, keeping the current rules if any of them is invalid.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let rules = load_rules(&self.config)?;
+        *self.rules.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(rules);
+        Ok(())
+    }
+
+    /// Checks a normalized destination URL against the current rules.
+    pub fn check(&self, url: &str) -> Result<(), BlockedError> {
+        let rules = Arc::clone(&self.rules.read().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        if let Some(prefix) =
+            rules.denied_prefixes.iter().find(|prefix| url.starts_with(prefix.as_str()))
+        {
+            return Err(BlockedError::Prefix(prefix.clone()));
+        }
+        let Some(host) =
+            Url::parse(url).ok().and_then(|url| url.host().map(|host| host_name(&host)))
+        else {
+            return Ok(());
+        };
+        if matches_domain(&rules.denied_domains, &host) {
+            return Err(BlockedError::Domain(host));
+        }
+        if !rules.allowed_domains.is_empty() && !matches_domain(&rules.allowed_domains, &host) {
+            return Err(BlockedError::NotAllowed(host));
+        }
+        Ok(())
+    }
+
+    fn files(&self) -> impl Iterator<Item = &Path> {
+        self.config.deny.iter().chain(&self.config.allow).map(PathBuf::as_path)
+    }
+}
+
+/// Spawns a task that reloads the blocklist whenever one of its files is modified.
+pub fn spawn(blocklist: Blocklist, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut modified = modification_times(&blocklist);
+        loop {
+            interval.tick().await;
+            let current = modification_times(&blocklist);
+            if current == modified {
+                continue;
+            }
+            modified = current;
+            match blocklist.reload() {
+                Ok(()) => tracing::info!("Reloaded blocklist"),
+                Err(err) => {
+                    tracing::warn!(error = ?err, "Reloading blocklist failed, keeping previous rules")
+                }
+            }
+        }
+    })
+}
+
+fn modification_times(blocklist: &Blocklist) -> Vec<Option<SystemTime>> {
+    blocklist
+        .files()
+        .map(|path| path.metadata().and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+fn load_rules(config: &BlocklistConfig) -> anyhow::Result<Rules> {
+    let mut rules = Rules::default();
+    for path in &config.deny {
+        let entries = read_entries(path)?;
+        rules.denied_domains.extend(entries.domains);
+        rules.denied_prefixes.extend(entries.prefixes);
+    }
+    for path in &config.allow {
+        let entries = read_entries(path)?;
+        ensure!(
+            entries.prefixes.is_empty(),
+            "Allowlist {} must only contain domains",
+            path.display()
+        );
+        rules.allowed_domains.extend(entries.domains);
+    }
+    Ok(rules)
+}
+
+fn read_entries(path: &Path) -> anyhow::Result<Entries> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Read {}", path.display()))?;
+    let mut entries = Entries::default();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        parse_line(line, &mut entries).with_context(|| {
+            format!("Invalid entry on line {} of {}", number + 1, path.display())
+        })?;
+    }
+    Ok(entries)
+}
+
+fn parse_line(line: &str, entries: &mut Entries) -> anyhow::Result<()> {
+    let mut tokens = line.split_whitespace();
+    match (tokens.next(), tokens.next()) {
+        (None, _) => {}
+        (Some(prefix), None) if prefix.contains("://") => {
+            let prefix =
+                Url::parse(prefix).with_context(|| format!("Invalid URL prefix {prefix:?}"))?;
+            entries.prefixes.push(prefix.into());
+        }
+        (Some(domain), None) => {
+            entries.domains.insert(parse_domain(domain)?);
+        }
+        (Some(address), Some(name)) if address.parse::<IpAddr>().is_ok() => {
+            let names = std::iter::once(name).chain(tokens);
+            for name in names.filter(|name| !HOSTS_LOCAL_NAMES.contains(name)) {
+                entries.domains.insert(parse_domain(name)?);
+            }
+        }
+        _ => bail!("Expected a domain, a URL prefix or a hosts file entry"),
+    }
+    Ok(())
+}
+
+// Normalize a domain like the `url` crate does for hosts, so entries compare equal to URL hosts
+fn parse_domain(domain: &str) -> anyhow::Result<String> {
+    let domain = domain.trim_start_matches("*.").trim_matches('.');
+    if let Ok(address) = domain.parse::<IpAddr>() {
+        return Ok(address.to_string());
+    }
+    let host = Host::parse(domain).with_context(|| format!("Invalid domain {domain:?}"))?;
+    Ok(host_name(&host))
+}
+
+fn host_name<S: AsRef<str>>(host: &Host<S>) -> String {
+    match host {
+        Host::Domain(domain) => domain.as_ref().trim_end_matches('.').to_string(),
+        Host::Ipv4(address) => address.to_string(),
+        Host::Ipv6(address) => address.to_string(),
+    }
+}
+
+// Whether `host` or one of its parent domains is in `domains`
+fn matches_domain(domains: &HashSet<String>, host: &str) -> bool {
+    let mut candidate = host;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> anyhow::Result<Entries> {
+        let mut entries = Entries::default();
+        for line in lines {
+            parse_line(line, &mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn parses_domains() {
+        let entries =
+            parse(&["Example.COM", "*.evil.test", "trailing.dot.", "bücher.example"]).unwrap();
+        let expected = ["example.com", "evil.test", "trailing.dot", "xn--bcher-kva.example"];
+        assert_eq!(entries.domains, expected.into_iter().map(String::from).collect());
+        assert!(entries.prefixes.is_empty());
+    }
+
+    #[test]
+    fn parses_url_prefixes() {
+        let entries = parse(&["https://Example.com/phish"]).unwrap();
+        assert_eq!(entries.prefixes, ["https://example.com/phish"]);
+        assert!(entries.domains.is_empty());
+    }
+
+    #[test]
+    fn parses_hosts_file_entries() {
+        let entries =
+            parse(&["0.0.0.0 ads.test tracker.test", "127.0.0.1 localhost", "::1 ip6-localhost"])
+                .unwrap();
+        let expected = ["ads.test", "tracker.test"];
+        assert_eq!(entries.domains, expected.into_iter().map(String::from).collect());
+    }
+
+    #[test]
+    fn skips_empty_lines() {
+        let entries = parse(&[""]).unwrap();
+        assert!(entries.domains.is_empty() && entries.prefixes.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(parse(&["two words"]).is_err());
+        assert!(parse(&["https://"]).is_err());
+        assert!(parse(&["exa%mple.com"]).is_err());
+    }
+}
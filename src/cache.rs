@@ -0,0 +1,194 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use time::OffsetDateTime;
+
+use crate::model::RedirectTarget;
+
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// Maximum number of cached aliases, the least recently used ones are evicted first.
+    pub capacity: NonZeroUsize,
+    /// How long a resolved link is served from the cache.
+    pub ttl: Duration,
+    /// How long an unknown alias is remembered as such.
+    pub negative_ttl: Duration,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// `None` for aliases that do not exist.
+    target: Option<RedirectTarget>,
+    expires: Instant,
+}
+
+/// Bounded cache of resolved aliases, letting redirects skip the database.
+///
+/// Only links whose redirects do not change per request are cached: click-limited links consume a
+/// click in the database and password-protected ones depend on the client.
+#[derive(Clone, Debug)]
+pub struct RedirectCache {
+    entries: Arc<Mutex<LruCache<String, Entry>>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl RedirectCache {
+    #[must_use]
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(LruCache::new(config.capacity))),
+            ttl: config.ttl,
+            negative_ttl: config.negative_ttl,
+        }
+    }
+
+    /// Returns `Some(None)` for aliases cached as unknown and `None` if `id` is not cached.
+    #[must_use]
+    pub fn get(&self, id: &str) -> Option<Option<RedirectTarget>> {
+        let mut entries = self.lock();
+        match entries.get(id) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.target.clone()),
+            Some(_) => {
+                entries.pop(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches `target` if it is cacheable, no longer than until the link expires.
+    pub fn insert(&self, id: &str, target: &RedirectTarget) {
+        if target.limited || target.password_protected {
+            return;
+        }
+        let mut ttl = self.ttl;
+        if let Some(expires_at) = target.expires_at {
+            let remaining = (expires_at - OffsetDateTime::now_utc()).max(time::Duration::ZERO);
+            ttl = ttl.min(remaining.unsigned_abs());
+        }
+        if ttl.is_zero() {
+            return;
+        }
+        let entry = Entry { target: Some(target.clone()), expires: Instant::now() + ttl };
+        self.lock().put(id.to_string(), entry);
+    }
+
+    /// Remembers that `id` does not exist.
+    pub fn insert_missing(&self, id: &str) {
+        if self.negative_ttl.is_zero() {
+            return;
+        }
+        let entry = Entry { target: None, expires: Instant::now() + self.negative_ttl };
+        self.lock().put(id.to_string(), entry);
+    }
+
+    pub fn invalidate(&self, id: &str) {
+        self.lock().pop(id);
+    }
+
+    /// Drops all entries, for when invalidations may have been missed.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destination::QueryForwarding;
+
+    fn cache(capacity: usize, negative_ttl: Duration) -> RedirectCache {
+        RedirectCache::new(CacheConfig {
+            capacity: NonZeroUsize::new(capacity).unwrap(),
+            ttl: Duration::from_secs(60),
+            negative_ttl,
+        })
+    }
+
+    fn target(url: &str) -> RedirectTarget {
+        RedirectTarget {
+            url: url.to_string(),
+            redirect_type: None,
+            expires_at: None,
+            limited: false,
+            forward_path: false,
+            forward_query: QueryForwarding::Off,
+            password_protected: false,
+        }
+    }
+
+    fn cached_url(cache: &RedirectCache, id: &str) -> Option<Option<String>> {
+        cache.get(id).map(|target| target.map(|target| target.url))
+    }
+
+    #[test]
+    fn evicts_least_recently_used_aliases() {
+        let cache = cache(2, Duration::from_secs(60));
+        cache.insert("a", &target("https://example.com/a"));
+        cache.insert("b", &target("https://example.com/b"));
+        assert!(cache.get("a").is_some());
+        cache.insert("c", &target("https://example.com/c"));
+
+        assert_eq!(cached_url(&cache, "a"), Some(Some("https://example.com/a".to_string())));
+        assert_eq!(cached_url(&cache, "b"), None);
+        assert_eq!(cached_url(&cache, "c"), Some(Some("https://example.com/c".to_string())));
+    }
+
+    #[test]
+    fn skips_links_that_change_per_request() {
+        let cache = cache(2, Duration::from_secs(60));
+        cache
+            .insert("limited", &RedirectTarget { limited: true, ..target("https://example.com/") });
+        let protected =
+            RedirectTarget { password_protected: true, ..target("https://example.com/") };
+        cache.insert("protected", &protected);
+        let expired = OffsetDateTime::now_utc() - time::Duration::seconds(1);
+        cache.insert(
+            "expired",
+            &RedirectTarget { expires_at: Some(expired), ..target("https://example.com/") },
+        );
+
+        assert!(cache.get("limited").is_none());
+        assert!(cache.get("protected").is_none());
+        assert!(cache.get("expired").is_none());
+    }
+
+    #[test]
+    fn remembers_unknown_aliases() {
+        let cache = cache(2, Duration::from_secs(60));
+        cache.insert_missing("missing");
+        assert_eq!(cached_url(&cache, "missing"), Some(None));
+
+        let cache = self::cache(2, Duration::ZERO);
+        cache.insert_missing("missing");
+        assert_eq!(cached_url(&cache, "missing"), None);
+    }
+
+    #[test]
+    fn drops_expired_entries() {
+        let cache = cache(2, Duration::from_millis(1));
+        cache.insert_missing("missing");
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(cached_url(&cache, "missing"), None);
+    }
+
+    #[test]
+    fn invalidates_single_aliases() {
+        let cache = cache(2, Duration::from_secs(60));
+        cache.insert("a", &target("https://example.com/a"));
+        cache.insert_missing("b");
+        cache.invalidate("a");
+        assert_eq!(cached_url(&cache, "a"), None);
+        assert_eq!(cached_url(&cache, "b"), Some(None));
+
+        cache.clear();
+        assert_eq!(cached_url(&cache, "b"), None);
+    }
+}
@@ -1,47 +1,811 @@
 use deadpool_postgres::GenericClient;
+use time::OffsetDateTime;
 use tokio_postgres::types::Type;
 
+use crate::destination::QueryForwarding;
+use crate::model::{
+    ApiKey, Click, ClickBucket, ClickCount, ClickDimension, ClickValue, Granularity, LinkChanges,
+    LinkCursor, LinkDetails, LinkFilter, LinkOwner, LinkRevision, LinkSort, LinkSummary, NewLink,
+    RedirectTarget, RedirectType, Resolved, UpdatedLink,
+};
+use crate::store::link_summary_columns;
+
+/// Channel notified with the alias of every created, changed or deleted link, delivered when the
+/// transaction making the change commits.
+pub const LINK_CHANNEL: &str = "link_changed";
+
+impl RedirectType {
+    fn to_column(redirect_type: Option<Self>) -> Option<i16> {
+        redirect_type.map(|redirect_type| redirect_type.code() as i16)
+    }
+
+    fn from_column(code: Option<i16>) -> Option<Self> {
+        code.and_then(|code| Self::try_from(code as u16).ok())
+    }
+}
+
+impl Granularity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Month => "month",
+        }
+    }
+}
+
 #[tracing::instrument(skip(client))]
-pub async fn create_link<C>(client: &C, id: &str, url: &str) -> Result<(), tokio_postgres::Error>
+pub async fn create_link<C>(client: &C, link: &NewLink<'_>) -> Result<(), tokio_postgres::Error>
 where
     C: GenericClient,
 {
-    const SQL: &str = "INSERT INTO link (id, url) VALUES ($1, $2)";
+    const SQL: &str = "INSERT INTO link
+            (id, url, expires_at, max_clicks, remaining_clicks, owner, redirect_type, forward_path,
+             forward_query, password_hash)
+        VALUES ($1, $2, $3, $4, $4, $5, $6, $7, $8, $9)";
+    const TYPES: &[Type] = &[
+        Type::TEXT,
+        Type::TEXT,
+        Type::TIMESTAMPTZ,
+        Type::INT4,
+        Type::INT8,
+        Type::INT2,
+        Type::BOOL,
+        Type::TEXT,
+        Type::TEXT,
+    ];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let redirect_type = RedirectType::to_column(link.redirect_type);
+    client
+        .execute(
+            &stmt,
+            &[
+                &link.id,
+                &link.url,
+                &link.expires_at,
+                &link.max_clicks,
+                &link.owner,
+                &redirect_type,
+                &link.forward_path,
+                &link.forward_query.as_str(),
+                &link.password_hash,
+            ],
+        )
+        .await?;
+    notify_link_changed(client, link.id).await
+}
+
+/// Notifies other instances through [`LINK_CHANNEL`] that the link `id` changed.
+async fn notify_link_changed<C>(client: &C, id: &str) -> Result<(), tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT pg_notify($1, $2)";
     const TYPES: &[Type] = &[Type::TEXT, Type::TEXT];
 
     let stmt = client.prepare_typed(SQL, TYPES).await?;
-    client.execute(&stmt, &[&id, &url]).await?;
+    client.execute(&stmt, &[&LINK_CHANNEL, &id]).await?;
     Ok(())
 }
 
+/// Moves the link `id` to the trash.
 #[tracing::instrument(skip(client))]
 pub async fn delete_link<C>(client: &C, id: &str) -> Result<(), tokio_postgres::Error>
 where
     C: GenericClient,
 {
-    const SQL: &str = "DELETE FROM link WHERE id = $1";
+    const SQL: &str = "UPDATE link SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL";
     const TYPES: &[Type] = &[Type::TEXT];
 
     let stmt = client.prepare_typed(SQL, TYPES).await?;
-    client.execute(&stmt, &[&id]).await?;
+    if client.execute(&stmt, &[&id]).await? > 0 {
+        notify_link_changed(client, id).await?;
+    }
     Ok(())
 }
 
+/// Takes the link `id` out of the trash, returning its new version or `None` if it is not in the
+/// trash.
 #[tracing::instrument(skip(client))]
-pub async fn get_link<C>(client: &C, id: &str) -> Result<String, tokio_postgres::Error>
+pub async fn restore_link<C>(client: &C, id: &str) -> Result<Option<i64>, tokio_postgres::Error>
 where
     C: GenericClient,
 {
-    const SQL: &str = "SELECT url FROM link WHERE id = $1";
+    const SQL: &str = "UPDATE link SET deleted_at = NULL, version = version + 1
+        WHERE id = $1 AND deleted_at IS NOT NULL RETURNING version";
     const TYPES: &[Type] = &[Type::TEXT];
 
     let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let Some(row) = client.query_opt(&stmt, &[&id]).await? else {
+        return Ok(None);
+    };
+    notify_link_changed(client, id).await?;
+    Ok(Some(row.try_get("version")?))
+}
+
+/// Hard-deletes the link `id` if it was moved to the trash more than `quarantine_secs` ago, freeing
+/// its alias for reuse.
+#[tracing::instrument(skip(client))]
+pub async fn release_deleted_link<C>(
+    client: &C,
+    id: &str,
+    quarantine_secs: f64,
+) -> Result<bool, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str =
+        "DELETE FROM link WHERE id = $1 AND deleted_at <= now() - make_interval(secs => $2)";
+    const TYPES: &[Type] = &[Type::TEXT, Type::FLOAT8];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    Ok(client.execute(&stmt, &[&id, &quarantine_secs]).await? > 0)
+}
+
+/// Hard-deletes links moved to the trash more than `retention_secs` ago, returning how many were
+/// removed.
+#[tracing::instrument(skip(client))]
+pub async fn purge_deleted_links<C>(
+    client: &C,
+    retention_secs: f64,
+) -> Result<u64, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "DELETE FROM link WHERE deleted_at <= now() - make_interval(secs => $1)";
+    const TYPES: &[Type] = &[Type::FLOAT8];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.execute(&stmt, &[&retention_secs]).await
+}
+
+/// Applies `changes` to the link `id` if its version is one of `versions` (any version if `None`).
+/// Returns `None` if the link does not exist or has a different version.
+#[tracing::instrument(skip(client))]
+pub async fn update_link<C>(
+    client: &C,
+    id: &str,
+    changes: &LinkChanges<'_>,
+    versions: Option<&[i64]>,
+) -> Result<Option<UpdatedLink>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "UPDATE link SET
+            url = coalesce($2, url),
+            expires_at = CASE WHEN $3 THEN $4 ELSE expires_at END,
+            max_clicks = CASE WHEN $5 THEN $6 ELSE max_clicks END,
+            remaining_clicks = CASE WHEN $5 THEN $6 ELSE remaining_clicks END,
+            redirect_type = CASE WHEN $8 THEN $9 ELSE redirect_type END,
+            forward_path = coalesce($10, forward_path),
+            forward_query = coalesce($11, forward_query),
+            password_hash = CASE WHEN $12 THEN $13 ELSE password_hash END,
+            version = version + 1
+        WHERE id = $1 AND deleted_at IS NULL AND ($7::bigint[] IS NULL OR version = ANY ($7))
+        RETURNING url, expires_at, max_clicks, redirect_type, forward_path, forward_query,
+                  password_hash IS NOT NULL AS password_protected, version";
+    const TYPES: &[Type] = &[
+        Type::TEXT,
+        Type::TEXT,
+        Type::BOOL,
+        Type::TIMESTAMPTZ,
+        Type::BOOL,
+        Type::INT4,
+        Type::INT8_ARRAY,
+        Type::BOOL,
+        Type::INT2,
+        Type::BOOL,
+        Type::TEXT,
+        Type::BOOL,
+        Type::TEXT,
+    ];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let row = client
+        .query_opt(
+            &stmt,
+            &[
+                &id,
+                &changes.url,
+                &changes.expires_at.is_some(),
+                &changes.expires_at.flatten(),
+                &changes.max_clicks.is_some(),
+                &changes.max_clicks.flatten(),
+                &versions,
+                &changes.redirect_type.is_some(),
+                &RedirectType::to_column(changes.redirect_type.flatten()),
+                &changes.forward_path,
+                &changes.forward_query.map(QueryForwarding::as_str),
+                &changes.password_hash.is_some(),
+                &changes.password_hash.flatten(),
+            ],
+        )
+        .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    notify_link_changed(client, id).await?;
+    Ok(Some(UpdatedLink {
+        url: row.try_get("url")?,
+        expires_at: row.try_get("expires_at")?,
+        max_clicks: row.try_get("max_clicks")?,
+        redirect_type: RedirectType::from_column(row.try_get("redirect_type")?),
+        forward_path: row.try_get("forward_path")?,
+        forward_query: QueryForwarding::from_str_lossy(row.try_get("forward_query")?),
+        password_protected: row.try_get("password_protected")?,
+        version: row.try_get("version")?,
+    }))
+}
+
+/// Records `url` as the next revision of the link `id` unless it equals the latest revision.
+/// Returns the new revision number, call it in the transaction changing the link.
+#[tracing::instrument(skip(client))]
+pub async fn insert_link_revision<C>(
+    client: &C,
+    id: &str,
+    url: &str,
+    author: Option<i64>,
+) -> Result<Option<i32>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "INSERT INTO link_revision (link_id, revision, url, author)
+        SELECT $1, coalesce(max(revision), 0) + 1, $2, $3 FROM link_revision WHERE link_id = $1
+        HAVING (array_agg(url ORDER BY revision DESC))[1] IS DISTINCT FROM $2
+        RETURNING revision";
+    const TYPES: &[Type] = &[Type::TEXT, Type::TEXT, Type::INT8];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client
+        .query_opt(&stmt, &[&id, &url, &author])
+        .await?
+        .map(|row| row.try_get("revision"))
+        .transpose()
+}
+
+#[tracing::instrument(skip(client))]
+pub async fn list_link_revisions<C>(
+    client: &C,
+    id: &str,
+) -> Result<Vec<LinkRevision>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str =
+        "SELECT r.revision, r.url, r.author, api_key.name AS author_name, r.created_at
+        FROM link_revision AS r LEFT JOIN api_key ON api_key.id = r.author
+        WHERE r.link_id = $1 ORDER BY r.revision DESC";
+    const TYPES: &[Type] = &[Type::TEXT];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let rows = client.query(&stmt, &[&id]).await?;
+    rows.iter()
+        .map(|row| {
+            Ok(LinkRevision {
+                revision: row.try_get("revision")?,
+                url: row.try_get("url")?,
+                author: row.try_get("author")?,
+                author_name: row.try_get("author_name")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Returns the destination of revision `revision` of the link `id`.
+#[tracing::instrument(skip(client))]
+pub async fn get_link_revision<C>(
+    client: &C,
+    id: &str,
+    revision: i32,
+) -> Result<Option<String>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT url FROM link_revision WHERE link_id = $1 AND revision = $2";
+    const TYPES: &[Type] = &[Type::TEXT, Type::INT4];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client
+        .query_opt(&stmt, &[&id, &revision])
+        .await?
+        .map(|row| row.try_get("url"))
+        .transpose()
+}
+
+#[tracing::instrument(skip(client))]
+pub async fn get_link_owner<C>(
+    client: &C,
+    id: &str,
+) -> Result<Option<LinkOwner>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT owner, deleted_at IS NOT NULL AS deleted FROM link WHERE id = $1";
+    const TYPES: &[Type] = &[Type::TEXT];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let Some(row) = client.query_opt(&stmt, &[&id]).await? else {
+        return Ok(None);
+    };
+    Ok(Some(LinkOwner { owner: row.try_get("owner")?, deleted: row.try_get("deleted")? }))
+}
+
+/// Resolves `id` for a redirect, consuming one click of click-limited links in the same statement
+/// so concurrent redirects cannot exceed `max_clicks`. Password-protected links are only resolved
+/// (and a click consumed) if `unlocked` is set.
+#[tracing::instrument(skip(client))]
+pub async fn get_link<C>(
+    client: &C,
+    id: &str,
+    unlocked: bool,
+) -> Result<Option<Resolved>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "WITH consumed AS (
+            UPDATE link SET remaining_clicks = remaining_clicks - 1
+            WHERE id = $1 AND deleted_at IS NULL AND remaining_clicks > 0
+              AND (expires_at IS NULL OR expires_at > now())
+              AND (password_hash IS NULL OR $2)
+            RETURNING id
+        )
+        SELECT link.url, link.redirect_type, link.expires_at, link.max_clicks IS NOT NULL AS limited,
+               link.forward_path, link.forward_query,
+               coalesce(link.expires_at <= now(), false) AS expired,
+               coalesce(link.remaining_clicks = 0, false)
+                   OR link.remaining_clicks IS NOT NULL AND consumed.id IS NULL AND NOT locked
+                   AS exhausted,
+               link.password_hash IS NOT NULL AS password_protected, locked
+        FROM link LEFT JOIN consumed ON consumed.id = link.id,
+             LATERAL (SELECT link.password_hash IS NOT NULL AND NOT $2 AS locked) AS l
+        WHERE link.id = $1 AND link.deleted_at IS NULL";
+    const TYPES: &[Type] = &[Type::TEXT, Type::BOOL];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+
+    let Some(row) = client.query_opt(&stmt, &[&id, &unlocked]).await? else {
+        return Ok(None);
+    };
+    if row.try_get("expired")? {
+        return Ok(Some(Resolved::Expired));
+    }
+    if row.try_get("exhausted")? {
+        return Ok(Some(Resolved::Exhausted));
+    }
+    if row.try_get("locked")? {
+        return Ok(Some(Resolved::Locked));
+    }
+    Ok(Some(Resolved::Active(RedirectTarget {
+        url: row.try_get("url")?,
+        redirect_type: RedirectType::from_column(row.try_get("redirect_type")?),
+        expires_at: row.try_get("expires_at")?,
+        limited: row.try_get("limited")?,
+        forward_path: row.try_get("forward_path")?,
+        forward_query: QueryForwarding::from_str_lossy(row.try_get("forward_query")?),
+        password_protected: row.try_get("password_protected")?,
+    })))
+}
+
+/// Reads the link `id` like [`get_link`] does, but without consuming a click and with all metadata.
+#[tracing::instrument(skip(client))]
+pub async fn get_link_details<C>(
+    client: &C,
+    id: &str,
+) -> Result<Option<LinkDetails>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT link.id, link.url, link.created_at, link.expires_at, link.max_clicks,
+               link.remaining_clicks, link.owner, api_key.name AS owner_name, link.redirect_type,
+               link.forward_path, link.forward_query,
+               link.password_hash IS NOT NULL AS password_protected,
+               (SELECT count(*) FROM click WHERE click.link_id = link.id) AS clicks
+        FROM link LEFT JOIN api_key ON api_key.id = link.owner
+        WHERE link.id = $1 AND link.deleted_at IS NULL";
+    const TYPES: &[Type] = &[Type::TEXT];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let Some(row) = client.query_opt(&stmt, &[&id]).await? else {
+        return Ok(None);
+    };
+    Ok(Some(LinkDetails {
+        id: row.try_get("id")?,
+        url: row.try_get("url")?,
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+        max_clicks: row.try_get("max_clicks")?,
+        remaining_clicks: row.try_get("remaining_clicks")?,
+        owner: row.try_get("owner")?,
+        owner_name: row.try_get("owner_name")?,
+        redirect_type: RedirectType::from_column(row.try_get("redirect_type")?),
+        forward_path: row.try_get("forward_path")?,
+        forward_query: QueryForwarding::from_str_lossy(row.try_get("forward_query")?),
+        password_protected: row.try_get("password_protected")?,
+        clicks: row.try_get("clicks")?,
+    }))
+}
+
+/// Returns the password hash of the link `id`, `None` if the link does not exist.
+#[tracing::instrument(skip(client))]
+pub async fn get_link_password<C>(
+    client: &C,
+    id: &str,
+) -> Result<Option<Option<String>>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT password_hash FROM link WHERE id = $1 AND deleted_at IS NULL";
+    const TYPES: &[Type] = &[Type::TEXT];
 
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
     let row = client.query_opt(&stmt, &[&id]).await?;
+    row.map(|row| row.try_get("password_hash")).transpose()
+}
+
+/// Deletes links that expired more than `retention_secs` ago, returning how many were removed.
+#[tracing::instrument(skip(client))]
+pub async fn purge_expired_links<C>(
+    client: &C,
+    retention_secs: f64,
+) -> Result<u64, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "DELETE FROM link WHERE expires_at <= now() - make_interval(secs => $1)";
+    const TYPES: &[Type] = &[Type::FLOAT8];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.execute(&stmt, &[&retention_secs]).await
+}
+
+/// Moves links that expired more than `retention_secs` ago into `link_archive` along with their
+/// revisions and clicks, returning how many were moved.
+#[tracing::instrument(skip(client))]
+pub async fn archive_expired_links<C>(
+    client: &C,
+    retention_secs: f64,
+) -> Result<u64, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    // The cascading deletes are not visible to the rest of the statement, which shares its snapshot
+    const SQL: &str = "WITH expired AS (
+            DELETE FROM link WHERE expires_at <= now() - make_interval(secs => $1) RETURNING *
+        )
+        INSERT INTO link_archive (id, data)
+        SELECT id, to_jsonb(expired) || jsonb_build_object(
+                'revisions', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(r) - 'link_id' ORDER BY r.revision), '[]')
+                    FROM link_revision r WHERE r.link_id = expired.id),
+                'clicks', (
+                    SELECT coalesce(
+                            jsonb_agg(to_jsonb(c) - 'id' - 'link_id' ORDER BY c.clicked_at), '[]')
+                    FROM click c WHERE c.link_id = expired.id))
+        FROM expired";
+    const TYPES: &[Type] = &[Type::FLOAT8];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.execute(&stmt, &[&retention_secs]).await
+}
+
+/// Inserts a batch of clicks, skipping clicks of links deleted in the meantime.
+#[tracing::instrument(skip_all, fields(count = clicks.len()))]
+pub async fn insert_clicks<C>(client: &C, clicks: &[Click]) -> Result<u64, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "INSERT INTO click
+            (link_id, clicked_at, referer, user_agent, client_ip, accept_language, country)
+        SELECT c.* FROM unnest($1, $2, $3, $4, $5, $6, $7)
+            AS c (link_id, clicked_at, referer, user_agent, client_ip, accept_language, country)
+        WHERE EXISTS (SELECT 1 FROM link WHERE link.id = c.link_id)";
+    const TYPES: &[Type] = &[
+        Type::TEXT_ARRAY,
+        Type::TIMESTAMPTZ_ARRAY,
+        Type::TEXT_ARRAY,
+        Type::TEXT_ARRAY,
+        Type::INET_ARRAY,
+        Type::TEXT_ARRAY,
+        Type::TEXT_ARRAY,
+    ];
+
+    let link_ids: Vec<_> = clicks.iter().map(|c| c.link_id.as_str()).collect();
+    let clicked_at: Vec<_> = clicks.iter().map(|c| c.clicked_at).collect();
+    let referers: Vec<_> = clicks.iter().map(|c| c.referer.as_deref()).collect();
+    let user_agents: Vec<_> = clicks.iter().map(|c| c.user_agent.as_deref()).collect();
+    let client_ips: Vec<_> = clicks.iter().map(|c| c.client_ip).collect();
+    let languages: Vec<_> = clicks.iter().map(|c| c.accept_language.as_deref()).collect();
+    let countries: Vec<_> = clicks.iter().map(|c| c.country.as_deref()).collect();
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client
+        .execute(
+            &stmt,
+            &[&link_ids, &clicked_at, &referers, &user_agents, &client_ips, &languages, &countries],
+        )
+        .await
+}
+
+/// Runs a trivial query to check the connection.
+pub async fn ping<C>(client: &C) -> Result<(), tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    client.query_one("SELECT 1", &[]).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(client))]
+pub async fn link_exists<C>(client: &C, id: &str) -> Result<bool, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT EXISTS (SELECT 1 FROM link WHERE id = $1 AND deleted_at IS NULL)";
+    const TYPES: &[Type] = &[Type::TEXT];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.query_one(&stmt, &[&id]).await?.try_get(0)
+}
+
+/// Counts the clicks of `id` within `[from, to)`, visitors are told apart by IP and user agent.
+#[tracing::instrument(skip(client))]
+pub async fn count_clicks<C>(
+    client: &C,
+    id: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<ClickCount, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT count(*) AS clicks,
+            count(DISTINCT (client_ip, user_agent)) AS unique_visitors
+        FROM click WHERE link_id = $1 AND clicked_at >= $2 AND clicked_at < $3";
+    const TYPES: &[Type] = &[Type::TEXT, Type::TIMESTAMPTZ, Type::TIMESTAMPTZ];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let row = client.query_one(&stmt, &[&id, &from, &to]).await?;
+    Ok(ClickCount {
+        clicks: row.try_get("clicks")?,
+        unique_visitors: row.try_get("unique_visitors")?,
+    })
+}
+
+/// Counts the clicks of `id` within `[from, to)` per UTC time bucket, omitting empty buckets.
+#[tracing::instrument(skip(client))]
+pub async fn click_series<C>(
+    client: &C,
+    id: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    granularity: Granularity,
+) -> Result<Vec<ClickBucket>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT date_trunc($4, clicked_at, 'UTC') AS bucket,
+            count(*) AS clicks,
+            count(DISTINCT (client_ip, user_agent)) AS unique_visitors
+        FROM click WHERE link_id = $1 AND clicked_at >= $2 AND clicked_at < $3
+        GROUP BY bucket ORDER BY bucket";
+    const TYPES: &[Type] = &[Type::TEXT, Type::TIMESTAMPTZ, Type::TIMESTAMPTZ, Type::TEXT];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let rows = client.query(&stmt, &[&id, &from, &to, &granularity.as_str()]).await?;
+    rows.iter()
+        .map(|row| {
+            Ok(ClickBucket {
+                bucket: row.try_get("bucket")?,
+                count: ClickCount {
+                    clicks: row.try_get("clicks")?,
+                    unique_visitors: row.try_get("unique_visitors")?,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Returns the `limit` most frequent values of `dimension` among the clicks of `id` within
+/// `[from, to)`.
+#[tracing::instrument(skip(client))]
+pub async fn top_click_values<C>(
+    client: &C,
+    id: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    dimension: ClickDimension,
+    limit: i64,
+) -> Result<Vec<ClickValue>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const REFERER_SQL: &str = "SELECT referer AS value, count(*) AS clicks
+        FROM click WHERE link_id = $1 AND clicked_at >= $2 AND clicked_at < $3
+        GROUP BY value ORDER BY clicks DESC, value LIMIT $4";
+    const USER_AGENT_SQL: &str = "SELECT user_agent AS value, count(*) AS clicks
+        FROM click WHERE link_id = $1 AND clicked_at >= $2 AND clicked_at < $3
+        GROUP BY value ORDER BY clicks DESC, value LIMIT $4";
+    const COUNTRY_SQL: &str = "SELECT country AS value, count(*) AS clicks
+        FROM click WHERE link_id = $1 AND clicked_at >= $2 AND clicked_at < $3
+        GROUP BY value ORDER BY clicks DESC, value LIMIT $4";
+    const TYPES: &[Type] = &[Type::TEXT, Type::TIMESTAMPTZ, Type::TIMESTAMPTZ, Type::INT8];
+
+    let sql = match dimension {
+        ClickDimension::Referer => REFERER_SQL,
+        ClickDimension::UserAgent => USER_AGENT_SQL,
+        ClickDimension::Country => COUNTRY_SQL,
+    };
+    let stmt = client.prepare_typed(sql, TYPES).await?;
+    let rows = client.query(&stmt, &[&id, &from, &to, &limit]).await?;
+    rows.iter()
+        .map(|row| Ok(ClickValue { value: row.try_get("value")?, clicks: row.try_get("clicks")? }))
+        .collect()
+}
+
+#[tracing::instrument(skip(client, key_hash))]
+pub async fn create_api_key<C>(
+    client: &C,
+    name: &str,
+    key_hash: &[u8],
+) -> Result<i64, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "INSERT INTO api_key (name, key_hash) VALUES ($1, $2) RETURNING id";
+    const TYPES: &[Type] = &[Type::TEXT, Type::BYTEA];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.query_one(&stmt, &[&name, &key_hash]).await?.try_get("id")
+}
+
+/// Looks up the non-revoked API key with the given hash.
+#[tracing::instrument(skip_all)]
+pub async fn find_api_key<C>(
+    client: &C,
+    key_hash: &[u8],
+) -> Result<Option<ApiKey>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = "SELECT id, name FROM api_key WHERE key_hash = $1 AND revoked_at IS NULL";
+    const TYPES: &[Type] = &[Type::BYTEA];
+
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    let Some(row) = client.query_opt(&stmt, &[&key_hash]).await? else {
+        return Ok(None);
+    };
+    Ok(Some(ApiKey { id: row.try_get("id")?, name: row.try_get("name")? }))
+}
+
+macro_rules! list_links_sql {
+    ($after:literal, $order:literal) => {
+        concat!(
+            "SELECT * FROM (
+                SELECT ",
+            link_summary_columns!(),
+            "
+                FROM link
+                WHERE ($1::text IS NULL OR lower(substring(link.url
+                        FROM '^[A-Za-z][A-Za-z0-9+.-]*://(?:[^@/?#]*@)?([^:/?#]+)')) = lower($1))
+                  AND ($2::bigint IS NULL OR link.owner = $2)
+                  AND ($3::timestamptz IS NULL OR link.created_at >= $3)
+                  AND ($4::timestamptz IS NULL OR link.created_at < $4)
+                  AND ($5::text IS NULL OR starts_with(link.id, $5))
+                  AND (link.deleted_at IS NOT NULL) = $10
+            ) AS l
+            WHERE $8::text IS NULL OR ",
+            $after,
+            " ORDER BY ",
+            $order,
+            " LIMIT $9"
+        )
+    };
+}
+
+/// Lists up to `limit` links matching `filter` in the order of `sort`, continuing after `after`.
+#[tracing::instrument(skip(client))]
+pub async fn list_links<C>(
+    client: &C,
+    filter: &LinkFilter<'_>,
+    sort: LinkSort,
+    descending: bool,
+    after: Option<&LinkCursor>,
+    limit: i64,
+) -> Result<Vec<LinkSummary>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const TYPES: &[Type] = &[
+        Type::TEXT,
+        Type::INT8,
+        Type::TIMESTAMPTZ,
+        Type::TIMESTAMPTZ,
+        Type::TEXT,
+        Type::TIMESTAMPTZ,
+        Type::INT8,
+        Type::TEXT,
+        Type::INT8,
+        Type::BOOL,
+    ];
+
+    let sql = match (sort, descending) {
+        (LinkSort::CreatedAt, false) => {
+            list_links_sql!("(l.created_at, l.id) > ($6, $8)", "l.created_at, l.id")
+        }
+        (LinkSort::CreatedAt, true) => {
+            list_links_sql!("(l.created_at, l.id) < ($6, $8)", "l.created_at DESC, l.id DESC")
+        }
+        (LinkSort::Clicks, false) => {
+            list_links_sql!("(l.clicks, l.id) > ($7, $8)", "l.clicks, l.id")
+        }
+        (LinkSort::Clicks, true) => {
+            list_links_sql!("(l.clicks, l.id) < ($7, $8)", "l.clicks DESC, l.id DESC")
+        }
+    };
+    let (after_created_at, after_clicks, after_id) = match after {
+        Some(LinkCursor::CreatedAt(created_at, id)) => (Some(*created_at), None, Some(id.as_str())),
+        Some(LinkCursor::Clicks(clicks, id)) => (None, Some(*clicks), Some(id.as_str())),
+        None => (None, None, None),
+    };
+
+    let stmt = client.prepare_typed(sql, TYPES).await?;
+    let rows = client
+        .query(
+            &stmt,
+            &[
+                &filter.host,
+                &filter.owner,
+                &filter.created_after,
+                &filter.created_before,
+                &filter.prefix,
+                &after_created_at,
+                &after_clicks,
+                &after_id,
+                &limit,
+                &filter.deleted,
+            ],
+        )
+        .await?;
+    rows.iter().map(link_summary).collect()
+}
+
+/// Reads the live link `id` as [`list_links`] lists it.
+#[tracing::instrument(skip(client))]
+pub async fn get_link_summary<C>(
+    client: &C,
+    id: &str,
+) -> Result<Option<LinkSummary>, tokio_postgres::Error>
+where
+    C: GenericClient,
+{
+    const SQL: &str = concat!(
+        "SELECT ",
+        link_summary_columns!(),
+        " FROM link WHERE link.id = $1 AND link.deleted_at IS NULL"
+    );
+    const TYPES: &[Type] = &[Type::TEXT];
 
-    Ok(row.and_then(|r| r.try_get::<_, String>("url").ok())
-        .unwrap_or_else(|| String::new()))
+    let stmt = client.prepare_typed(SQL, TYPES).await?;
+    client.query_opt(&stmt, &[&id]).await?.as_ref().map(link_summary).transpose()
+}
 
-    // let row = client.query_one(&stmt, &[&id]).await?;
-    // row.try_get("url")
+fn link_summary(row: &tokio_postgres::Row) -> Result<LinkSummary, tokio_postgres::Error> {
+    Ok(LinkSummary {
+        id: row.try_get("id")?,
+        url: row.try_get("url")?,
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+        max_clicks: row.try_get("max_clicks")?,
+        remaining_clicks: row.try_get("remaining_clicks")?,
+        owner: row.try_get("owner")?,
+        redirect_type: RedirectType::from_column(row.try_get("redirect_type")?),
+        forward_path: row.try_get("forward_path")?,
+        forward_query: QueryForwarding::from_str_lossy(row.try_get("forward_query")?),
+        password_protected: row.try_get("password_protected")?,
+        version: row.try_get("version")?,
+        deleted_at: row.try_get("deleted_at")?,
+        clicks: row.try_get("clicks")?,
+    })
 }
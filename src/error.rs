@@ -0,0 +1,184 @@
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use tokio_postgres::error::SqlState;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Errors returned by the HTTP handlers, rendered as RFC 7807 problem details.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Gone(String),
+    #[error("{0}")]
+    PreconditionFailed(String),
+    /// A field of the request body violates a validation rule.
+    #[error("{detail}")]
+    Unprocessable { field: &'static str, rule: &'static str, detail: String },
+    #[error("Database unavailable")]
+    Unavailable(#[source] anyhow::Error),
+    #[error("Database error")]
+    Database(#[source] anyhow::Error),
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// Problem details object as described in RFC 7807.
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    title: &'a str,
+    status: u16,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<&'a str>,
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Gone(_) => StatusCode::GONE,
+            Self::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Self::Unprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Database(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!(error = ?self, "Request failed");
+        }
+        let (field, rule) = match self {
+            Self::Unprocessable { field, rule, .. } => (Some(*field), Some(*rule)),
+            _ => (None, None),
+        };
+        let problem = Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            field,
+            rule,
+        };
+        let mut response = HttpResponse::build(status);
+        if let Self::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.content_type(PROBLEM_JSON).json(problem)
+    }
+}
+
+/// A value of the request body rejected by a validation rule, answered with
+/// `422 Unprocessable Entity`.
+pub trait Violation: std::error::Error {
+    /// Request field holding the rejected value.
+    const FIELD: &'static str;
+
+    /// Name of the violated rule, reported to clients next to the message.
+    fn rule(&self) -> &'static str;
+}
+
+impl<V: Violation> From<V> for Error {
+    fn from(err: V) -> Self {
+        Self::Unprocessable { field: V::FIELD, rule: err.rule(), detail: err.to_string() }
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        if let Some(db) = err.as_db_error() {
+            let code = db.code();
+            if *code == SqlState::UNIQUE_VIOLATION {
+                return Self::Conflict(db.detail().unwrap_or(db.message()).to_string());
+            }
+            if *code == SqlState::NO_DATA_FOUND {
+                return Self::NotFound(db.message().to_string());
+            }
+            if code.code().starts_with("08")
+                || *code == SqlState::ADMIN_SHUTDOWN
+                || *code == SqlState::CRASH_SHUTDOWN
+                || *code == SqlState::CANNOT_CONNECT_NOW
+            {
+                return Self::Unavailable(err.into());
+            }
+            return Self::Database(err.into());
+        }
+        if err.is_closed() {
+            return Self::Unavailable(err.into());
+        }
+        // tokio-postgres does not expose its error kind, `query_one` without rows is only
+        // recognizable by its message.
+        if err.to_string() == "query returned an unexpected number of rows" {
+            return Self::NotFound("Resource not found".to_string());
+        }
+        Self::Database(err.into())
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for Error {
+    fn from(err: deadpool_postgres::PoolError) -> Self {
+        use deadpool_postgres::PoolError;
+
+        match err {
+            PoolError::Backend(err) => match Self::from(err) {
+                Self::Database(err) => Self::Unavailable(err),
+                err => err,
+            },
+            PoolError::Timeout(_) | PoolError::Closed => Self::Unavailable(err.into()),
+            err => Self::Internal(format!("Database pool error: {err}")),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode;
+        use rusqlite::ffi::{SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_CONSTRAINT_UNIQUE};
+
+        match &err {
+            rusqlite::Error::SqliteFailure(code, message)
+                if matches!(
+                    code.extended_code,
+                    SQLITE_CONSTRAINT_PRIMARYKEY | SQLITE_CONSTRAINT_UNIQUE
+                ) =>
+            {
+                Self::Conflict(message.clone().unwrap_or_else(|| code.to_string()))
+            }
+            rusqlite::Error::SqliteFailure(code, _)
+                if matches!(code.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) =>
+            {
+                Self::Unavailable(err.into())
+            }
+            rusqlite::Error::QueryReturnedNoRows => {
+                Self::NotFound("Resource not found".to_string())
+            }
+            _ => Self::Database(err.into()),
+        }
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(err: r2d2::Error) -> Self {
+        Self::Unavailable(err.into())
+    }
+}
@@ -1,6 +1,17 @@
 #![deny(unsafe_code, unused_imports)]
 #![deny(clippy::all)]
 
+pub mod alias;
+pub mod analytics;
 pub mod api;
+pub mod blocklist;
+pub mod cache;
 pub mod database;
+pub mod destination;
+pub mod error;
+pub mod listener;
+pub mod model;
+pub mod password;
 pub mod state;
+pub mod store;
+pub mod sweeper;
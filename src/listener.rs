@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_postgres::AsyncMessage;
+
+use crate::cache::RedirectCache;
+use crate::database::LINK_CHANNEL;
+
+/// Spawns a task evicting links from `cache` when another instance changes them.
+///
+/// The task holds its own connection, since `LISTEN` is bound to a session and pooled connections
+/// are shared. It reconnects after `retry_delay` when the connection is lost and flushes the whole
+/// cache, as notifications sent in the meantime are gone.
+pub fn spawn(
+    config: tokio_postgres::Config,
+    cache: RedirectCache,
+    retry_delay: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match listen(&config, &cache).await {
+                Ok(()) => tracing::warn!("Link notification connection closed, reconnecting"),
+                Err(err) => {
+                    tracing::warn!(error = ?err, "Listening for link notifications failed, reconnecting")
+                }
+            }
+            cache.clear();
+            tokio::time::sleep(retry_delay).await;
+        }
+    })
+}
+
+async fn listen(
+    config: &tokio_postgres::Config,
+    cache: &RedirectCache,
+) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = config.connect(tokio_postgres::NoTls).await?;
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    // The connection has to be polled for the client to make progress and to receive notifications
+    let connection = tokio::spawn(async move {
+        loop {
+            match std::future::poll_fn(|cx| connection.poll_message(cx)).await {
+                Some(Ok(AsyncMessage::Notification(notification))) => {
+                    if sender.send(notification.payload().to_string()).is_err() {
+                        return Ok(());
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err),
+                None => return Ok(()),
+            }
+        }
+    });
+
+    client.batch_execute(&format!("LISTEN {LINK_CHANNEL}")).await?;
+    // Changes made before the listener was (re)connected were not notified
+    cache.clear();
+    tracing::info!("Listening for link notifications");
+    while let Some(id) = receiver.recv().await {
+        cache.invalidate(&id);
+    }
+    drop(client);
+    match connection.await {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!(error = ?err, "Link notification connection task failed");
+            Ok(())
+        }
+    }
+}
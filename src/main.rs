@@ -2,10 +2,26 @@
 #![deny(clippy::all)]
 
 use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use tracing::info;
-use url_shortener::state::State;
+use url_shortener::alias::{self, AliasGenerator, AliasPolicy};
+use url_shortener::analytics::{ClickRecorder, RecorderConfig, TrustedProxies};
+use url_shortener::blocklist::{self, Blocklist, BlocklistConfig};
+use url_shortener::cache::{CacheConfig, RedirectCache};
+use url_shortener::destination::{self, DestinationPolicy};
+use url_shortener::password::{PasswordConfig, PasswordGuard};
+use url_shortener::state::{NotFoundResponse, State};
+use url_shortener::store::{LinkStore, MemoryStore, PostgresStore, SqliteStore};
+use url_shortener::sweeper::{self, SweeperConfig};
+use url_shortener::{api, listener};
+
+/// `DB_CONNECTION` selecting the in-memory store instead of Postgres.
+const MEMORY_CONNECTION: &str = "memory://";
+/// Prefix of `DB_CONNECTION` selecting the SQLite store, followed by the database file path.
+const SQLITE_SCHEME: &str = "sqlite://";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -20,6 +36,61 @@ async fn main() -> anyhow::Result<()> {
 
     init_tracing()?;
 
+    let command: Vec<String> = std::env::args().skip(1).collect();
+
+    let connection_str =
+        std::env::var("DB_CONNECTION").context("Missing DB_CONNECTION environment variable")?;
+    let in_memory = connection_str == MEMORY_CONNECTION;
+    let timeout = match std::env::var("DB_POOL_TIMEOUT") {
+        Ok(secs) => secs.parse().context("Invalid DB_POOL_TIMEOUT environment variable")?,
+        Err(_) => 5,
+    };
+    let timeout = Duration::from_secs(timeout);
+    let (store, database_config): (Arc<dyn LinkStore>, _) = if in_memory {
+        (Arc::new(MemoryStore::new()), None)
+    } else if let Some(path) = connection_str.strip_prefix(SQLITE_SCHEME) {
+        let store = SqliteStore::open(path, timeout)
+            .with_context(|| format!("Open SQLite database {path}"))?;
+        (Arc::new(store), None)
+    } else {
+        let config: tokio_postgres::Config =
+            connection_str.parse().context("Invalid DB_CONNECTION environment variable")?;
+        let timeout = Some(timeout);
+        let mgr = deadpool_postgres::Manager::new(config.clone(), tokio_postgres::NoTls);
+        let pool = deadpool_postgres::Pool::builder(mgr)
+            .runtime(deadpool_postgres::Runtime::Tokio1)
+            .wait_timeout(timeout)
+            .create_timeout(timeout)
+            .build()
+            .context("Create database pool")?;
+        (Arc::new(PostgresStore::new(pool)), Some(config))
+    };
+    match command.as_slice() {
+        [] => {}
+        [command, _] if command == "create-api-key" && in_memory => {
+            anyhow::bail!(
+                "API keys of the in-memory store are lost on exit, one is created on startup"
+            );
+        }
+        [command, name] if command == "create-api-key" => {
+            let key = api::auth::generate_api_key();
+            store
+                .create_api_key(name, &api::auth::hash_api_key(&key))
+                .await
+                .context("Create API key")?;
+            println!("{key}");
+            return Ok(());
+        }
+        _ => anyhow::bail!("Usage: url-shortener [create-api-key <name>]"),
+    }
+    if in_memory {
+        let key = api::auth::generate_api_key();
+        store.create_api_key("default", &api::auth::hash_api_key(&key)).await?;
+        tracing::warn!("Storing links in memory, they are lost on exit");
+        // Printed once outside of tracing so the key does not end up in collected logs
+        eprintln!("API key of the in-memory store: {key}");
+    }
+
     let address = {
         let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
         let port: u16 = std::env::var("PORT")
@@ -30,21 +101,259 @@ async fn main() -> anyhow::Result<()> {
     };
     info!("Starting API on {address}");
 
-    let database = {
-        let connection_str =
-            std::env::var("DB_CONNECTION").context("Missing DB_CONNECTION environment variable")?;
-        let config: tokio_postgres::Config =
-            connection_str.parse().context("Invalid DB_CONNECTION environment variable")?;
-        let mgr = deadpool_postgres::Manager::new(config, tokio_postgres::NoTls);
-        deadpool_postgres::Pool::builder(mgr)
-            .runtime(deadpool_postgres::Runtime::Tokio1)
-            .build()
-            .context("Create database pool")?
+    let alias_generator = {
+        let alphabet = std::env::var("ID_ALPHABET").unwrap_or_else(|_| alias::BASE62.to_string());
+        let length = match std::env::var("ID_LENGTH") {
+            Ok(length) => length.parse().context("Invalid ID_LENGTH environment variable")?,
+            Err(_) => alias::DEFAULT_LENGTH,
+        };
+        AliasGenerator::new(&alphabet, length).context("Invalid ID_ALPHABET or ID_LENGTH")?
+    };
+    let alias_policy = {
+        let charset =
+            std::env::var("ALIAS_CHARSET").unwrap_or_else(|_| alias::DEFAULT_CHARSET.to_string());
+        let min_length = match std::env::var("ALIAS_MIN_LENGTH") {
+            Ok(length) => {
+                length.parse().context("Invalid ALIAS_MIN_LENGTH environment variable")?
+            }
+            Err(_) => alias::DEFAULT_MIN_LENGTH,
+        };
+        let max_length = match std::env::var("ALIAS_MAX_LENGTH") {
+            Ok(length) => {
+                length.parse().context("Invalid ALIAS_MAX_LENGTH environment variable")?
+            }
+            Err(_) => alias::DEFAULT_MAX_LENGTH,
+        };
+        let case = match std::env::var("ALIAS_CASE") {
+            Ok(case) => case.parse().context("Invalid ALIAS_CASE environment variable")?,
+            Err(_) => Default::default(),
+        };
+        let reserved = std::env::var("ALIAS_RESERVED").unwrap_or_default();
+        let blocklist = match std::env::var("ALIAS_BLOCKLIST") {
+            Ok(path) => std::fs::read_to_string(&path)
+                .with_context(|| format!("Read ALIAS_BLOCKLIST {path}"))?,
+            Err(_) => String::new(),
+        };
+        let policy = AliasPolicy::new(&charset, min_length, max_length)
+            .context("Invalid alias policy")?
+            .with_case_folding(case)
+            .with_reserved(api::RESERVED_ALIASES)
+            .with_reserved(reserved.split(',').map(str::trim).filter(|word| !word.is_empty()))
+            .with_blocklist(blocklist.lines().filter(|line| !line.starts_with('#')));
+        policy
+            .check_generator(&alias_generator)
+            .context("Invalid ID_ALPHABET or ID_LENGTH")?;
+        policy
+    };
+    let not_found = match (std::env::var("NOT_FOUND_REDIRECT"), std::env::var("NOT_FOUND_PAGE")) {
+        (Ok(url), _) => {
+            let url =
+                url::Url::parse(&url).context("Invalid NOT_FOUND_REDIRECT environment variable")?;
+            NotFoundResponse::Redirect(url.into())
+        }
+        (Err(_), Ok(path)) => {
+            let html = std::fs::read_to_string(&path)
+                .with_context(|| format!("Read NOT_FOUND_PAGE {path}"))?;
+            NotFoundResponse::Page(html.into())
+        }
+        _ => NotFoundResponse::Problem,
+    };
+    let destination_policy = {
+        let schemes = match std::env::var("ALLOWED_SCHEMES") {
+            Ok(schemes) => schemes.split(',').map(|s| s.trim().to_string()).collect(),
+            Err(_) => destination::DEFAULT_SCHEMES.iter().map(ToString::to_string).collect(),
+        };
+        let max_length = match std::env::var("MAX_URL_LENGTH") {
+            Ok(length) => length.parse().context("Invalid MAX_URL_LENGTH environment variable")?,
+            Err(_) => destination::DEFAULT_MAX_LENGTH,
+        };
+        DestinationPolicy::new(schemes, max_length)
+    };
+    let blocklist = {
+        let files = |name| match std::env::var(name) {
+            Ok(paths) => paths.split(',').map(|path| path.trim().into()).collect(),
+            Err(_) => Vec::new(),
+        };
+        let config =
+            BlocklistConfig { deny: files("BLOCKLIST_FILES"), allow: files("ALLOWLIST_FILES") };
+        Blocklist::load(config).context("Load BLOCKLIST_FILES or ALLOWLIST_FILES")?
+    };
+    let blocklist_reload = match std::env::var("BLOCKLIST_RELOAD_INTERVAL") {
+        Ok(secs) => {
+            secs.parse().context("Invalid BLOCKLIST_RELOAD_INTERVAL environment variable")?
+        }
+        Err(_) => 30,
+    };
+    if blocklist_reload > 0 {
+        blocklist::spawn(blocklist.clone(), Duration::from_secs(blocklist_reload));
+    }
+    let redirect_type = match std::env::var("REDIRECT_TYPE") {
+        Ok(code) => code.parse().context("Invalid REDIRECT_TYPE environment variable")?,
+        Err(_) => Default::default(),
     };
-    let state = State::new(database);
+    let redirect_max_age = match std::env::var("REDIRECT_MAX_AGE") {
+        Ok(secs) => secs.parse().context("Invalid REDIRECT_MAX_AGE environment variable")?,
+        Err(_) => 24 * 60 * 60,
+    };
+    let password_guard = {
+        let defaults = PasswordConfig::default();
+        let cookie_secret = match std::env::var("LINK_COOKIE_SECRET") {
+            Ok(secret) => secret.into_bytes(),
+            Err(_) => {
+                info!("LINK_COOKIE_SECRET is not set, unlocked links are forgotten on restart");
+                defaults.cookie_secret
+            }
+        };
+        let cookie_ttl = match std::env::var("LINK_COOKIE_TTL") {
+            Ok(secs) => secs.parse().context("Invalid LINK_COOKIE_TTL environment variable")?,
+            Err(_) => defaults.cookie_ttl.as_secs(),
+        };
+        let max_alias_failures = match std::env::var("PASSWORD_MAX_ALIAS_FAILURES") {
+            Ok(count) => count
+                .parse()
+                .context("Invalid PASSWORD_MAX_ALIAS_FAILURES environment variable")?,
+            Err(_) => defaults.max_alias_failures,
+        };
+        let max_ip_failures = match std::env::var("PASSWORD_MAX_IP_FAILURES") {
+            Ok(count) => {
+                count.parse().context("Invalid PASSWORD_MAX_IP_FAILURES environment variable")?
+            }
+            Err(_) => defaults.max_ip_failures,
+        };
+        let failure_window = match std::env::var("PASSWORD_FAILURE_WINDOW") {
+            Ok(secs) => {
+                secs.parse().context("Invalid PASSWORD_FAILURE_WINDOW environment variable")?
+            }
+            Err(_) => defaults.failure_window.as_secs(),
+        };
+        PasswordGuard::new(PasswordConfig {
+            cookie_secret,
+            cookie_ttl: Duration::from_secs(cookie_ttl),
+            max_alias_failures,
+            max_ip_failures,
+            failure_window: Duration::from_secs(failure_window),
+        })
+    };
+    let trash_retention = match std::env::var("TRASH_RETENTION") {
+        Ok(secs) => secs.parse().context("Invalid TRASH_RETENTION environment variable")?,
+        Err(_) => 30 * 24 * 60 * 60,
+    };
+    let alias_quarantine = match std::env::var("ALIAS_QUARANTINE") {
+        Ok(secs) => secs.parse().context("Invalid ALIAS_QUARANTINE environment variable")?,
+        Err(_) => trash_retention,
+    };
+    anyhow::ensure!(
+        alias_quarantine <= trash_retention,
+        "ALIAS_QUARANTINE must not exceed TRASH_RETENTION, purged aliases are free for reuse"
+    );
+
+    let sweep_interval = match std::env::var("SWEEP_INTERVAL") {
+        Ok(secs) => secs.parse().context("Invalid SWEEP_INTERVAL environment variable")?,
+        Err(_) => 300,
+    };
+    if sweep_interval > 0 {
+        let retention = match std::env::var("EXPIRED_RETENTION") {
+            Ok(secs) => secs.parse().context("Invalid EXPIRED_RETENTION environment variable")?,
+            Err(_) => 7 * 24 * 60 * 60,
+        };
+        let action = match std::env::var("EXPIRED_ACTION") {
+            Ok(action) => action.parse().context("Invalid EXPIRED_ACTION environment variable")?,
+            Err(_) => Default::default(),
+        };
+        sweeper::spawn(
+            store.clone(),
+            SweeperConfig {
+                interval: Duration::from_secs(sweep_interval),
+                retention: Duration::from_secs(retention),
+                action,
+                trash_retention: Duration::from_secs(trash_retention),
+            },
+        );
+    }
+
+    let click_capacity = match std::env::var("CLICK_BUFFER") {
+        Ok(capacity) => capacity.parse().context("Invalid CLICK_BUFFER environment variable")?,
+        Err(_) => 10_000,
+    };
+    let country_header = match std::env::var("COUNTRY_HEADER") {
+        Ok(name) => Some(name.parse().context("Invalid COUNTRY_HEADER environment variable")?),
+        Err(_) => None,
+    };
+    let trusted_proxies = match std::env::var("TRUSTED_PROXIES") {
+        Ok(addresses) => TrustedProxies::new(
+            addresses
+                .split(',')
+                .map(|address| address.trim().parse())
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid TRUSTED_PROXIES environment variable")?,
+        ),
+        Err(_) => TrustedProxies::default(),
+    };
+    let click_recorder = (click_capacity > 0).then(|| {
+        let config = RecorderConfig {
+            capacity: click_capacity,
+            batch_size: 500,
+            country_header,
+            trusted_proxies: trusted_proxies.clone(),
+        };
+        ClickRecorder::spawn(store.clone(), config)
+    });
+
+    let cache_size = match std::env::var("REDIRECT_CACHE_SIZE") {
+        Ok(size) => size.parse().context("Invalid REDIRECT_CACHE_SIZE environment variable")?,
+        Err(_) => 10_000,
+    };
+    let cache_ttl = match std::env::var("REDIRECT_CACHE_TTL") {
+        Ok(secs) => secs.parse().context("Invalid REDIRECT_CACHE_TTL environment variable")?,
+        Err(_) => 60,
+    };
+    let cache_negative_ttl = match std::env::var("REDIRECT_CACHE_NEGATIVE_TTL") {
+        Ok(secs) => secs
+            .parse()
+            .context("Invalid REDIRECT_CACHE_NEGATIVE_TTL environment variable")?,
+        Err(_) => 5,
+    };
+    let redirect_cache = std::num::NonZeroUsize::new(cache_size).map(|capacity| {
+        RedirectCache::new(CacheConfig {
+            capacity,
+            ttl: Duration::from_secs(cache_ttl),
+            negative_ttl: Duration::from_secs(cache_negative_ttl),
+        })
+    });
+    let cache_reconnect = match std::env::var("REDIRECT_CACHE_RECONNECT_DELAY") {
+        Ok(secs) => secs
+            .parse()
+            .context("Invalid REDIRECT_CACHE_RECONNECT_DELAY environment variable")?,
+        Err(_) => 5,
+    };
+    if let (Some(redirect_cache), Some(database_config)) = (&redirect_cache, database_config) {
+        listener::spawn(
+            database_config,
+            redirect_cache.clone(),
+            Duration::from_secs(cache_reconnect),
+        );
+    }
+
+    let mut state = State::new(store)
+        .with_alias_generator(alias_generator)
+        .with_alias_policy(alias_policy)
+        .with_not_found(not_found)
+        .with_alias_quarantine(Duration::from_secs(alias_quarantine))
+        .with_destination_policy(destination_policy)
+        .with_blocklist(blocklist)
+        .with_redirect_type(redirect_type)
+        .with_redirect_max_age(Duration::from_secs(redirect_max_age))
+        .with_password_guard(password_guard)
+        .with_trusted_proxies(trusted_proxies);
+    if let Some(click_recorder) = click_recorder {
+        state = state.with_click_recorder(click_recorder);
+    }
+    if let Some(redirect_cache) = redirect_cache {
+        state = state.with_redirect_cache(redirect_cache);
+    }
 
     let listener = TcpListener::bind(address)?;
-    url_shortener::api::listen(listener, state)?.await?;
+    api::listen(listener, state)?.await?;
     Ok(())
 }
 
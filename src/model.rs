@@ -0,0 +1,305 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::destination::QueryForwarding;
+
+/// Attributes of a link to insert.
+#[derive(Debug)]
+pub struct NewLink<'a> {
+    pub id: &'a str,
+    pub url: &'a str,
+    pub expires_at: Option<OffsetDateTime>,
+    pub max_clicks: Option<i32>,
+    /// API key creating the link.
+    pub owner: Option<i64>,
+    pub redirect_type: Option<RedirectType>,
+    pub forward_path: bool,
+    pub forward_query: QueryForwarding,
+    /// Argon2 hash of the link password.
+    pub password_hash: Option<&'a str>,
+}
+
+/// HTTP status a link redirects with, serialized as its status code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum RedirectType {
+    MovedPermanently,
+    #[default]
+    Found,
+    TemporaryRedirect,
+    PermanentRedirect,
+}
+
+impl RedirectType {
+    #[must_use]
+    pub fn code(self) -> u16 {
+        match self {
+            Self::MovedPermanently => 301,
+            Self::Found => 302,
+            Self::TemporaryRedirect => 307,
+            Self::PermanentRedirect => 308,
+        }
+    }
+
+    /// Whether clients and search engines may remember the redirect.
+    #[must_use]
+    pub fn is_permanent(self) -> bool {
+        matches!(self, Self::MovedPermanently | Self::PermanentRedirect)
+    }
+}
+
+impl TryFrom<u16> for RedirectType {
+    type Error = String;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        match code {
+            301 => Ok(Self::MovedPermanently),
+            302 => Ok(Self::Found),
+            307 => Ok(Self::TemporaryRedirect),
+            308 => Ok(Self::PermanentRedirect),
+            _ => Err(format!("Unsupported redirect type {code}, expected 301, 302, 307 or 308")),
+        }
+    }
+}
+
+impl From<RedirectType> for u16 {
+    fn from(redirect_type: RedirectType) -> Self {
+        redirect_type.code()
+    }
+}
+
+impl FromStr for RedirectType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code: u16 = s.parse()?;
+        Self::try_from(code).map_err(anyhow::Error::msg)
+    }
+}
+
+/// Where and how an active link redirects.
+#[derive(Clone, Debug)]
+pub struct RedirectTarget {
+    pub url: String,
+    /// `None` if the link uses the server default.
+    pub redirect_type: Option<RedirectType>,
+    pub expires_at: Option<OffsetDateTime>,
+    /// Whether the link has a `max_clicks` limit.
+    pub limited: bool,
+    pub forward_path: bool,
+    pub forward_query: QueryForwarding,
+    /// Whether the client had to unlock the link with its password.
+    pub password_protected: bool,
+}
+
+/// Outcome of resolving an alias for a redirect.
+#[derive(Debug)]
+pub enum Resolved {
+    Active(RedirectTarget),
+    /// The link has a password and the client did not unlock it.
+    Locked,
+    Expired,
+    /// The link reached its `max_clicks`.
+    Exhausted,
+}
+
+/// Changes to apply to a link, `None` fields are left unchanged.
+#[derive(Debug, Default)]
+pub struct LinkChanges<'a> {
+    pub url: Option<&'a str>,
+    pub expires_at: Option<Option<OffsetDateTime>>,
+    /// Also resets the remaining clicks.
+    pub max_clicks: Option<Option<i32>>,
+    pub redirect_type: Option<Option<RedirectType>>,
+    pub forward_path: Option<bool>,
+    pub forward_query: Option<QueryForwarding>,
+    pub password_hash: Option<Option<&'a str>>,
+}
+
+impl LinkChanges<'_> {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.url.is_none()
+            && self.expires_at.is_none()
+            && self.max_clicks.is_none()
+            && self.redirect_type.is_none()
+            && self.forward_path.is_none()
+            && self.forward_query.is_none()
+            && self.password_hash.is_none()
+    }
+}
+
+/// A link after an update.
+#[derive(Debug)]
+pub struct UpdatedLink {
+    pub url: String,
+    pub expires_at: Option<OffsetDateTime>,
+    pub max_clicks: Option<i32>,
+    pub redirect_type: Option<RedirectType>,
+    pub forward_path: bool,
+    pub forward_query: QueryForwarding,
+    pub password_protected: bool,
+    pub version: i64,
+}
+
+/// A former or current destination of a link.
+#[derive(Debug, Serialize)]
+pub struct LinkRevision {
+    pub revision: i32,
+    pub url: String,
+    pub author: Option<i64>,
+    pub author_name: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Ownership of a link, including links in the trash.
+#[derive(Debug)]
+pub struct LinkOwner {
+    /// API key owning the link, `None` for links created without a key.
+    pub owner: Option<i64>,
+    pub deleted: bool,
+}
+
+/// Full metadata of a link, for inspecting it without following it.
+#[derive(Debug, Serialize)]
+pub struct LinkDetails {
+    pub id: String,
+    pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    pub max_clicks: Option<i32>,
+    pub remaining_clicks: Option<i32>,
+    pub owner: Option<i64>,
+    pub owner_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_type: Option<RedirectType>,
+    pub forward_path: bool,
+    pub forward_query: QueryForwarding,
+    pub password_protected: bool,
+    pub clicks: i64,
+}
+
+/// A recorded redirect of a link.
+#[derive(Clone, Debug)]
+pub struct Click {
+    pub link_id: String,
+    pub clicked_at: OffsetDateTime,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    pub accept_language: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Size of the time buckets of a click series.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Hour,
+    #[default]
+    Day,
+    Month,
+}
+
+/// Request attribute clicks can be grouped by.
+#[derive(Clone, Copy, Debug)]
+pub enum ClickDimension {
+    Referer,
+    UserAgent,
+    Country,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClickCount {
+    pub clicks: i64,
+    pub unique_visitors: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClickBucket {
+    #[serde(with = "time::serde::rfc3339")]
+    pub bucket: OffsetDateTime,
+    #[serde(flatten)]
+    pub count: ClickCount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClickValue {
+    pub value: Option<String>,
+    pub clicks: i64,
+}
+
+/// An API key authorized to manage links.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+}
+
+/// Filters of a link listing, `None` fields match all links.
+#[derive(Debug, Default)]
+pub struct LinkFilter<'a> {
+    /// Host of the destination URL, compared case-insensitively.
+    pub host: Option<&'a str>,
+    pub owner: Option<i64>,
+    pub created_after: Option<OffsetDateTime>,
+    pub created_before: Option<OffsetDateTime>,
+    pub prefix: Option<&'a str>,
+    /// List the trash instead of the live links.
+    pub deleted: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkSort {
+    #[default]
+    CreatedAt,
+    /// Best-effort ordering by click count: counts are computed per page and change while clicks
+    /// are recorded, so paging through links being clicked can skip or repeat some of them.
+    Clicks,
+}
+
+/// Position after the last link of a listing page.
+#[derive(Debug, Deserialize, Serialize)]
+pub enum LinkCursor {
+    CreatedAt(OffsetDateTime, String),
+    Clicks(i64, String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkSummary {
+    pub id: String,
+    pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    pub max_clicks: Option<i32>,
+    pub remaining_clicks: Option<i32>,
+    pub owner: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_type: Option<RedirectType>,
+    pub forward_path: bool,
+    pub forward_query: QueryForwarding,
+    pub password_protected: bool,
+    pub version: i64,
+    #[serde(with = "time::serde::rfc3339::option", skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<OffsetDateTime>,
+    pub clicks: i64,
+}
+
+impl LinkSummary {
+    #[must_use]
+    pub fn cursor(&self, sort: LinkSort) -> LinkCursor {
+        match sort {
+            LinkSort::CreatedAt => LinkCursor::CreatedAt(self.created_at, self.id.clone()),
+            LinkSort::Clicks => LinkCursor::Clicks(self.clicks, self.id.clone()),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use time::OffsetDateTime;
+
+use crate::error::Error;
+
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+/// Number of tracked aliases and addresses above which expired failure windows are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Hashes a link password into a PHC string.
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    if password.is_empty() || password.len() > MAX_PASSWORD_LENGTH {
+        return Err(Error::Unprocessable {
+            field: "password",
+            rule: "length",
+            detail: format!("Password must be between 1 and {MAX_PASSWORD_LENGTH} bytes long"),
+        });
+    }
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+        .map_err(|err| Error::Internal(format!("Encoding password salt failed: {err}")))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| Error::Internal(format!("Hashing password failed: {err}")))
+}
+
+/// Checks `password` against a hash made by [`hash_password`].
+#[must_use]
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(err) => {
+            tracing::warn!("Invalid password hash: {err}");
+            false
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PasswordConfig {
+    /// Key signing the cookies of unlocked links, links have to be unlocked again when it changes.
+    pub cookie_secret: Vec<u8>,
+    /// How long an unlocked link can be followed without entering the password again.
+    pub cookie_ttl: Duration,
+    /// Failed attempts per alias within `failure_window` before further attempts are refused.
+    pub max_alias_failures: u32,
+    /// Failed attempts per client IP within `failure_window` before further attempts are refused.
+    pub max_ip_failures: u32,
+    pub failure_window: Duration,
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            cookie_secret: rand::random::<[u8; 32]>().to_vec(),
+            cookie_ttl: Duration::from_secs(60 * 60),
+            max_alias_failures: 20,
+            max_ip_failures: 5,
+            failure_window: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// Failed attempts of one alias or client since `started`.
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    started: Instant,
+}
+
+#[derive(Debug, Default)]
+struct FailureLog {
+    aliases: HashMap<String, Failures>,
+    addresses: HashMap<IpAddr, Failures>,
+}
+
+/// Signs unlock cookies of password-protected links and limits failed password attempts.
+#[derive(Clone, Debug)]
+pub struct PasswordGuard {
+    config: Arc<PasswordConfig>,
+    failures: Arc<Mutex<FailureLog>>,
+}
+
+impl PasswordGuard {
+    #[must_use]
+    pub fn new(config: PasswordConfig) -> Self {
+        Self { config: Arc::new(config), failures: Arc::default() }
+    }
+
+    #[must_use]
+    pub fn cookie_ttl(&self) -> Duration {
+        self.config.cookie_ttl
+    }
+
+    /// Name of the cookie unlocking `id`.
+    #[must_use]
+    pub fn cookie_name(id: &str) -> String {
+        format!("unlock_{}", URL_SAFE_NO_PAD.encode(id))
+    }
+
+    /// Cookie value unlocking `id` until the configured TTL elapses or its password hash changes.
+    #[must_use]
+    pub fn sign(&self, id: &str, password_hash: &str) -> String {
+        let expires =
+            OffsetDateTime::now_utc().unix_timestamp() + self.config.cookie_ttl.as_secs() as i64;
+        let signature =
+            URL_SAFE_NO_PAD.encode(self.mac(id, password_hash, expires).finalize().into_bytes());
+        format!("{expires}.{signature}")
+    }
+
+    /// Whether `value` was made by [`sign`](Self::sign) for `id` with `password_hash` and has not
+    /// expired.
+    #[must_use]
+    pub fn verify(&self, id: &str, password_hash: &str, value: &str) -> bool {
+        let Some((expires, signature)) = value.split_once('.') else {
+            return false;
+        };
+        let (Ok(expires), Ok(signature)) =
+            (expires.parse::<i64>(), URL_SAFE_NO_PAD.decode(signature))
+        else {
+            return false;
+        };
+        expires > OffsetDateTime::now_utc().unix_timestamp()
+            && self.mac(id, password_hash, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, id: &str, password_hash: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.config.cookie_secret)
+            .expect("HMAC accepts keys of any length");
+        mac.update(id.as_bytes());
+        mac.update(&[0]);
+        mac.update(password_hash.as_bytes());
+        mac.update(&[0]);
+        mac.update(&expires.to_be_bytes());
+        mac
+    }
+
+    /// Returns how long to wait if `id` or `address` had too many failed attempts recently.
+    #[must_use]
+    pub fn retry_after(&self, id: &str, address: Option<IpAddr>) -> Option<Duration> {
+        let log = self.failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let window = self.config.failure_window;
+        let alias = log
+            .aliases
+            .get(id)
+            .and_then(|failures| blocked_for(failures, self.config.max_alias_failures, window));
+        let address = address
+            .and_then(|address| log.addresses.get(&address))
+            .and_then(|failures| blocked_for(failures, self.config.max_ip_failures, window));
+        alias.max(address)
+    }
+
+    pub fn record_failure(&self, id: &str, address: Option<IpAddr>) {
+        let mut log = self.failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let window = self.config.failure_window;
+        let now = Instant::now();
+        count_failure(
+            log.aliases.entry(id.to_string()).or_insert(Failures { count: 0, started: now }),
+            window,
+        );
+        if let Some(address) = address {
+            count_failure(
+                log.addresses.entry(address).or_insert(Failures { count: 0, started: now }),
+                window,
+            );
+        }
+        if log.aliases.len() + log.addresses.len() > PRUNE_THRESHOLD {
+            log.aliases.retain(|_, failures| failures.started.elapsed() < window);
+            log.addresses.retain(|_, failures| failures.started.elapsed() < window);
+        }
+    }
+}
+
+impl Default for PasswordGuard {
+    fn default() -> Self {
+        Self::new(PasswordConfig::default())
+    }
+}
+
+fn blocked_for(failures: &Failures, max_failures: u32, window: Duration) -> Option<Duration> {
+    let elapsed = failures.started.elapsed();
+    (failures.count >= max_failures && elapsed < window).then(|| window - elapsed)
+}
+
+fn count_failure(failures: &mut Failures, window: Duration) {
+    if failures.started.elapsed() >= window {
+        *failures = Failures { count: 0, started: Instant::now() };
+    }
+    failures.count += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA";
+
+    fn guard(cookie_ttl: Duration) -> PasswordGuard {
+        PasswordGuard::new(PasswordConfig {
+            cookie_secret: b"secret".to_vec(),
+            cookie_ttl,
+            ..PasswordConfig::default()
+        })
+    }
+
+    #[test]
+    fn verifies_signed_cookies() {
+        let guard = guard(Duration::from_secs(60));
+        let value = guard.sign("abc", HASH);
+        assert!(guard.verify("abc", HASH, &value));
+    }
+
+    #[test]
+    fn rejects_cookies_of_other_links_and_passwords() {
+        let guard = guard(Duration::from_secs(60));
+        let value = guard.sign("abc", HASH);
+        assert!(!guard.verify("abd", HASH, &value));
+        assert!(!guard.verify("abc", "$argon2id$other", &value));
+    }
+
+    #[test]
+    fn rejects_cookies_signed_with_another_secret() {
+        let value = guard(Duration::from_secs(60)).sign("abc", HASH);
+        assert!(!PasswordGuard::default().verify("abc", HASH, &value));
+    }
+
+    #[test]
+    fn rejects_expired_and_tampered_cookies() {
+        let guard = guard(Duration::ZERO);
+        assert!(!guard.verify("abc", HASH, &guard.sign("abc", HASH)));
+
+        let guard = self::guard(Duration::from_secs(60));
+        let value = guard.sign("abc", HASH);
+        let (expires, signature) = value.split_once('.').unwrap();
+        let extended = format!("{}.{signature}", expires.parse::<i64>().unwrap() + 3600);
+        assert!(!guard.verify("abc", HASH, &extended));
+        assert!(!guard.verify("abc", HASH, expires));
+        assert!(!guard.verify("abc", HASH, ""));
+    }
+
+    #[test]
+    fn verifies_hashed_passwords() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(verify_password(&hash, "correct horse"));
+        assert!(!verify_password(&hash, "wrong horse"));
+        assert!(hash_password("").is_err());
+    }
+
+    #[test]
+    fn limits_failed_attempts_per_alias_and_address() {
+        let guard = PasswordGuard::new(PasswordConfig {
+            max_alias_failures: 3,
+            max_ip_failures: 2,
+            ..PasswordConfig::default()
+        });
+        let address = Some(IpAddr::from([192, 0, 2, 1]));
+        guard.record_failure("abc", address);
+        assert!(guard.retry_after("abc", address).is_none());
+        guard.record_failure("abc", address);
+        assert!(guard.retry_after("xyz", address).is_some());
+        assert!(guard.retry_after("abc", None).is_none());
+        guard.record_failure("abc", None);
+        assert!(guard.retry_after("abc", None).is_some());
+    }
+}
@@ -1,17 +1,202 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::alias::{AliasGenerator, AliasPolicy};
+use crate::analytics::{ClickRecorder, TrustedProxies};
+use crate::api::RESERVED_ALIASES;
+use crate::blocklist::Blocklist;
+use crate::cache::RedirectCache;
+use crate::destination::DestinationPolicy;
+use crate::model::RedirectType;
+use crate::password::PasswordGuard;
+use crate::store::LinkStore;
+
+/// How requests for unknown aliases are answered.
+#[derive(Clone, Debug, Default)]
+pub enum NotFoundResponse {
+    /// Plain `404 Not Found` problem details.
+    #[default]
+    Problem,
+    /// Redirect to a site-wide fallback URL.
+    Redirect(String),
+    /// `404 Not Found` with a custom HTML page.
+    Page(Arc<str>),
+}
+
 #[derive(Clone)]
 pub struct State {
-    database: deadpool_postgres::Pool,
+    store: Arc<dyn LinkStore>,
+    alias_generator: AliasGenerator,
+    alias_policy: AliasPolicy,
+    not_found: NotFoundResponse,
+    click_recorder: Option<ClickRecorder>,
+    alias_quarantine: Duration,
+    destination_policy: DestinationPolicy,
+    blocklist: Blocklist,
+    redirect_type: RedirectType,
+    redirect_max_age: Duration,
+    password_guard: PasswordGuard,
+    redirect_cache: Option<RedirectCache>,
+    trusted_proxies: TrustedProxies,
 }
 
 impl State {
     #[must_use]
-    pub fn new(database: deadpool_postgres::Pool) -> Self {
-        Self { database }
+    pub fn new(store: Arc<dyn LinkStore>) -> Self {
+        Self {
+            store,
+            alias_generator: AliasGenerator::default(),
+            alias_policy: AliasPolicy::default().with_reserved(RESERVED_ALIASES),
+            not_found: NotFoundResponse::default(),
+            click_recorder: None,
+            alias_quarantine: Duration::ZERO,
+            destination_policy: DestinationPolicy::default(),
+            blocklist: Blocklist::default(),
+            redirect_type: RedirectType::default(),
+            redirect_max_age: Duration::from_secs(24 * 60 * 60),
+            password_guard: PasswordGuard::default(),
+            redirect_cache: None,
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_alias_generator(mut self, alias_generator: AliasGenerator) -> Self {
+        self.alias_generator = alias_generator;
+        self
+    }
+
+    #[must_use]
+    pub fn with_alias_policy(mut self, alias_policy: AliasPolicy) -> Self {
+        self.alias_policy = alias_policy;
+        self
     }
 
-    pub async fn database_client(
-        &self,
-    ) -> Result<deadpool_postgres::Client, deadpool_postgres::PoolError> {
-        self.database.get().await
+    #[must_use]
+    pub fn with_not_found(mut self, not_found: NotFoundResponse) -> Self {
+        self.not_found = not_found;
+        self
+    }
+
+    #[must_use]
+    pub fn with_click_recorder(mut self, click_recorder: ClickRecorder) -> Self {
+        self.click_recorder = Some(click_recorder);
+        self
+    }
+
+    /// How long the alias of a deleted link cannot be registered again.
+    #[must_use]
+    pub fn with_alias_quarantine(mut self, alias_quarantine: Duration) -> Self {
+        self.alias_quarantine = alias_quarantine;
+        self
+    }
+
+    #[must_use]
+    pub fn with_destination_policy(mut self, destination_policy: DestinationPolicy) -> Self {
+        self.destination_policy = destination_policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /// Redirect type of links created without one.
+    #[must_use]
+    pub fn with_redirect_type(mut self, redirect_type: RedirectType) -> Self {
+        self.redirect_type = redirect_type;
+        self
+    }
+
+    /// How long clients may cache permanent redirects.
+    #[must_use]
+    pub fn with_redirect_max_age(mut self, redirect_max_age: Duration) -> Self {
+        self.redirect_max_age = redirect_max_age;
+        self
+    }
+
+    #[must_use]
+    pub fn with_password_guard(mut self, password_guard: PasswordGuard) -> Self {
+        self.password_guard = password_guard;
+        self
+    }
+
+    #[must_use]
+    pub fn with_redirect_cache(mut self, redirect_cache: RedirectCache) -> Self {
+        self.redirect_cache = Some(redirect_cache);
+        self
+    }
+
+    #[must_use]
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    #[must_use]
+    pub fn store(&self) -> &dyn LinkStore {
+        self.store.as_ref()
+    }
+
+    #[must_use]
+    pub fn alias_generator(&self) -> &AliasGenerator {
+        &self.alias_generator
+    }
+
+    #[must_use]
+    pub fn alias_policy(&self) -> &AliasPolicy {
+        &self.alias_policy
+    }
+
+    #[must_use]
+    pub fn not_found(&self) -> &NotFoundResponse {
+        &self.not_found
+    }
+
+    #[must_use]
+    pub fn click_recorder(&self) -> Option<&ClickRecorder> {
+        self.click_recorder.as_ref()
+    }
+
+    #[must_use]
+    pub fn alias_quarantine(&self) -> Duration {
+        self.alias_quarantine
+    }
+
+    #[must_use]
+    pub fn destination_policy(&self) -> &DestinationPolicy {
+        &self.destination_policy
+    }
+
+    #[must_use]
+    pub fn blocklist(&self) -> &Blocklist {
+        &self.blocklist
+    }
+
+    #[must_use]
+    pub fn redirect_type(&self) -> RedirectType {
+        self.redirect_type
+    }
+
+    #[must_use]
+    pub fn redirect_max_age(&self) -> Duration {
+        self.redirect_max_age
+    }
+
+    #[must_use]
+    pub fn password_guard(&self) -> &PasswordGuard {
+        &self.password_guard
+    }
+
+    #[must_use]
+    pub fn redirect_cache(&self) -> Option<&RedirectCache> {
+        self.redirect_cache.as_ref()
+    }
+
+    #[must_use]
+    pub fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
     }
 }
@@ -0,0 +1,589 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use time::{OffsetDateTime, Time};
+
+use super::LinkStore;
+use crate::destination::QueryForwarding;
+use crate::error::Error;
+use crate::model::{
+    ApiKey, Click, ClickBucket, ClickCount, ClickDimension, ClickValue, Granularity, LinkChanges,
+    LinkCursor, LinkDetails, LinkFilter, LinkOwner, LinkRevision, LinkSort, LinkSummary, NewLink,
+    RedirectTarget, RedirectType, Resolved, UpdatedLink,
+};
+
+/// Link store keeping everything in process memory, for tests and trying the service out.
+///
+/// It behaves like [`PostgresStore`](super::PostgresStore), but everything is lost when the process
+/// exits and instances do not share links.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    memory: Mutex<Memory>,
+}
+
+#[derive(Debug, Default)]
+struct Memory {
+    api_keys: Vec<StoredApiKey>,
+    links: HashMap<String, StoredLink>,
+    /// Revisions of each link, oldest first.
+    revisions: HashMap<String, Vec<StoredRevision>>,
+    clicks: HashMap<String, Vec<Click>>,
+    /// Expired links moved out of `links` by the sweeper.
+    archive: Vec<RemovedLink>,
+}
+
+#[derive(Debug)]
+struct StoredApiKey {
+    id: i64,
+    name: String,
+    key_hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct StoredLink {
+    id: String,
+    url: String,
+    expires_at: Option<OffsetDateTime>,
+    max_clicks: Option<i32>,
+    remaining_clicks: Option<i32>,
+    owner: Option<i64>,
+    redirect_type: Option<RedirectType>,
+    forward_path: bool,
+    forward_query: QueryForwarding,
+    password_hash: Option<String>,
+    created_at: OffsetDateTime,
+    version: i64,
+    deleted_at: Option<OffsetDateTime>,
+}
+
+impl StoredLink {
+    fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[derive(Debug)]
+struct StoredRevision {
+    revision: i32,
+    url: String,
+    author: Option<i64>,
+    created_at: OffsetDateTime,
+}
+
+/// A link taken out of the store together with its revisions and clicks.
+// Only kept so the archive holds what the SQL stores archive, nothing reads it back
+#[allow(dead_code)]
+#[derive(Debug)]
+struct RemovedLink {
+    link: StoredLink,
+    revisions: Vec<StoredRevision>,
+    clicks: Vec<Click>,
+}
+
+impl MemoryStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Memory> {
+        self.memory.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Memory {
+    fn api_key_name(&self, id: Option<i64>) -> Option<String> {
+        let id = id?;
+        self.api_keys.iter().find(|key| key.id == id).map(|key| key.name.clone())
+    }
+
+    fn live_link(&self, id: &str) -> Option<&StoredLink> {
+        self.links.get(id).filter(|link| link.deleted_at.is_none())
+    }
+
+    fn click_count(&self, id: &str) -> i64 {
+        self.clicks.get(id).map_or(0, |clicks| clicks.len() as i64)
+    }
+
+    fn clicks_between(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> impl Iterator<Item = &Click> {
+        let clicks = self.clicks.get(id).map(Vec::as_slice).unwrap_or_default();
+        clicks
+            .iter()
+            .filter(move |click| click.clicked_at >= from && click.clicked_at < to)
+    }
+
+    /// Removes a link along with its revisions and clicks.
+    fn remove_link(&mut self, id: &str) -> Option<RemovedLink> {
+        Some(RemovedLink {
+            link: self.links.remove(id)?,
+            revisions: self.revisions.remove(id).unwrap_or_default(),
+            clicks: self.clicks.remove(id).unwrap_or_default(),
+        })
+    }
+
+    /// Removes the links matching `predicate`, returning them.
+    fn remove_links(&mut self, predicate: impl Fn(&StoredLink) -> bool) -> Vec<RemovedLink> {
+        let ids: Vec<_> = self
+            .links
+            .values()
+            .filter(|link| predicate(link))
+            .map(|link| link.id.clone())
+            .collect();
+        ids.iter().filter_map(|id| self.remove_link(id)).collect()
+    }
+
+    fn insert_revision(&mut self, id: &str, url: &str, author: Option<i64>) {
+        let revisions = self.revisions.entry(id.to_string()).or_default();
+        let latest = revisions.last();
+        if latest.is_some_and(|latest| latest.url == url) {
+            return;
+        }
+        let revision = latest.map_or(1, |latest| latest.revision + 1);
+        revisions.push(StoredRevision {
+            revision,
+            url: url.to_string(),
+            author,
+            created_at: OffsetDateTime::now_utc(),
+        });
+    }
+
+    fn summary(&self, link: &StoredLink) -> LinkSummary {
+        LinkSummary {
+            id: link.id.clone(),
+            url: link.url.clone(),
+            created_at: link.created_at,
+            expires_at: link.expires_at,
+            max_clicks: link.max_clicks,
+            remaining_clicks: link.remaining_clicks,
+            owner: link.owner,
+            redirect_type: link.redirect_type,
+            forward_path: link.forward_path,
+            forward_query: link.forward_query,
+            password_protected: link.password_hash.is_some(),
+            version: link.version,
+            deleted_at: link.deleted_at,
+            clicks: self.click_count(&link.id),
+        }
+    }
+}
+
+// Whether `link` matches all set fields of `filter`
+fn matches(link: &StoredLink, filter: &LinkFilter<'_>) -> bool {
+    let host = || {
+        url::Url::parse(&link.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+    };
+    filter
+        .host
+        .is_none_or(|filter| host().is_some_and(|host| host.eq_ignore_ascii_case(filter)))
+        && filter.owner.is_none_or(|owner| link.owner == Some(owner))
+        && filter.created_after.is_none_or(|after| link.created_at >= after)
+        && filter.created_before.is_none_or(|before| link.created_at < before)
+        && filter.prefix.is_none_or(|prefix| link.id.starts_with(prefix))
+        && link.deleted_at.is_some() == filter.deleted
+}
+
+// Position of `link` relative to `cursor` in ascending order
+fn compare_to_cursor(link: &LinkSummary, cursor: &LinkCursor) -> Ordering {
+    match cursor {
+        LinkCursor::CreatedAt(created_at, id) => {
+            (link.created_at, link.id.as_str()).cmp(&(*created_at, id.as_str()))
+        }
+        LinkCursor::Clicks(clicks, id) => {
+            (link.clicks, link.id.as_str()).cmp(&(*clicks, id.as_str()))
+        }
+    }
+}
+
+// Start of the UTC time bucket containing `at`
+fn bucket(at: OffsetDateTime, granularity: Granularity) -> OffsetDateTime {
+    let at = at.to_offset(time::UtcOffset::UTC);
+    let date = match granularity {
+        Granularity::Month => at.date().replace_day(1).expect("every month has a first day"),
+        Granularity::Hour | Granularity::Day => at.date(),
+    };
+    let time = match granularity {
+        Granularity::Hour => Time::from_hms(at.hour(), 0, 0).expect("hours of a time are valid"),
+        Granularity::Day | Granularity::Month => Time::MIDNIGHT,
+    };
+    date.with_time(time).assume_utc()
+}
+
+fn count(clicks: &[&Click]) -> ClickCount {
+    let visitors: HashSet<_> = clicks
+        .iter()
+        .map(|click| (click.client_ip, click.user_agent.as_deref()))
+        .collect();
+    ClickCount { clicks: clicks.len() as i64, unique_visitors: visitors.len() as i64 }
+}
+
+#[async_trait]
+impl LinkStore for MemoryStore {
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &[u8]) -> Result<i64, Error> {
+        let mut memory = self.lock();
+        if memory.api_keys.iter().any(|key| key.key_hash == key_hash) {
+            return Err(Error::Conflict("API key already exists".to_string()));
+        }
+        let id = memory.api_keys.len() as i64 + 1;
+        memory.api_keys.push(StoredApiKey {
+            id,
+            name: name.to_string(),
+            key_hash: key_hash.to_vec(),
+        });
+        Ok(id)
+    }
+
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, Error> {
+        let memory = self.lock();
+        let key = memory.api_keys.iter().find(|key| key.key_hash == key_hash);
+        Ok(key.map(|key| ApiKey { id: key.id, name: key.name.clone() }))
+    }
+
+    async fn create_link(&self, link: &NewLink<'_>, quarantine: Duration) -> Result<(), Error> {
+        let mut memory = self.lock();
+        let now = OffsetDateTime::now_utc();
+        let released = memory
+            .links
+            .get(link.id)
+            .and_then(|link| link.deleted_at)
+            .is_some_and(|deleted_at| deleted_at <= now - quarantine);
+        if released {
+            memory.remove_link(link.id);
+        }
+        if memory.links.contains_key(link.id) {
+            return Err(Error::Conflict(format!("Key (id)=({}) already exists.", link.id)));
+        }
+        memory.links.insert(
+            link.id.to_string(),
+            StoredLink {
+                id: link.id.to_string(),
+                url: link.url.to_string(),
+                expires_at: link.expires_at,
+                max_clicks: link.max_clicks,
+                remaining_clicks: link.max_clicks,
+                owner: link.owner,
+                redirect_type: link.redirect_type,
+                forward_path: link.forward_path,
+                forward_query: link.forward_query,
+                password_hash: link.password_hash.map(str::to_string),
+                created_at: now,
+                version: 1,
+                deleted_at: None,
+            },
+        );
+        memory.insert_revision(link.id, link.url, link.owner);
+        Ok(())
+    }
+
+    async fn get_link(&self, id: &str, unlocked: bool) -> Result<Option<Resolved>, Error> {
+        let mut memory = self.lock();
+        let Some(link) = memory.links.get_mut(id).filter(|link| link.deleted_at.is_none()) else {
+            return Ok(None);
+        };
+        let expired = link.is_expired(OffsetDateTime::now_utc());
+        let locked = link.password_hash.is_some() && !unlocked;
+        let remaining = link.remaining_clicks;
+        let consumed = match &mut link.remaining_clicks {
+            Some(remaining) if *remaining > 0 && !expired && !locked => {
+                *remaining -= 1;
+                true
+            }
+            _ => false,
+        };
+        if expired {
+            return Ok(Some(Resolved::Expired));
+        }
+        if remaining == Some(0) || (remaining.is_some() && !consumed && !locked) {
+            return Ok(Some(Resolved::Exhausted));
+        }
+        if locked {
+            return Ok(Some(Resolved::Locked));
+        }
+        Ok(Some(Resolved::Active(RedirectTarget {
+            url: link.url.clone(),
+            redirect_type: link.redirect_type,
+            expires_at: link.expires_at,
+            limited: link.max_clicks.is_some(),
+            forward_path: link.forward_path,
+            forward_query: link.forward_query,
+            password_protected: link.password_hash.is_some(),
+        })))
+    }
+
+    async fn get_link_details(&self, id: &str) -> Result<Option<LinkDetails>, Error> {
+        let memory = self.lock();
+        let Some(link) = memory.live_link(id) else {
+            return Ok(None);
+        };
+        Ok(Some(LinkDetails {
+            id: link.id.clone(),
+            url: link.url.clone(),
+            created_at: link.created_at,
+            expires_at: link.expires_at,
+            max_clicks: link.max_clicks,
+            remaining_clicks: link.remaining_clicks,
+            owner: link.owner,
+            owner_name: memory.api_key_name(link.owner),
+            redirect_type: link.redirect_type,
+            forward_path: link.forward_path,
+            forward_query: link.forward_query,
+            password_protected: link.password_hash.is_some(),
+            clicks: memory.click_count(id),
+        }))
+    }
+
+    async fn get_link_summary(&self, id: &str) -> Result<Option<LinkSummary>, Error> {
+        let memory = self.lock();
+        Ok(memory.live_link(id).map(|link| memory.summary(link)))
+    }
+
+    async fn get_link_password(&self, id: &str) -> Result<Option<Option<String>>, Error> {
+        Ok(self.lock().live_link(id).map(|link| link.password_hash.clone()))
+    }
+
+    async fn get_link_owner(&self, id: &str) -> Result<Option<LinkOwner>, Error> {
+        let memory = self.lock();
+        let link = memory.links.get(id);
+        Ok(link.map(|link| LinkOwner { owner: link.owner, deleted: link.deleted_at.is_some() }))
+    }
+
+    async fn link_exists(&self, id: &str) -> Result<bool, Error> {
+        Ok(self.lock().live_link(id).is_some())
+    }
+
+    async fn delete_link(&self, id: &str) -> Result<(), Error> {
+        if let Some(link) = self.lock().links.get_mut(id).filter(|link| link.deleted_at.is_none()) {
+            link.deleted_at = Some(OffsetDateTime::now_utc());
+        }
+        Ok(())
+    }
+
+    async fn restore_link(&self, id: &str) -> Result<Option<i64>, Error> {
+        let mut memory = self.lock();
+        let Some(link) = memory.links.get_mut(id).filter(|link| link.deleted_at.is_some()) else {
+            return Ok(None);
+        };
+        link.deleted_at = None;
+        link.version += 1;
+        Ok(Some(link.version))
+    }
+
+    async fn update_link(
+        &self,
+        id: &str,
+        changes: &LinkChanges<'_>,
+        versions: Option<&[i64]>,
+        author: Option<i64>,
+    ) -> Result<Option<UpdatedLink>, Error> {
+        let mut memory = self.lock();
+        let Some(link) = memory.links.get_mut(id).filter(|link| link.deleted_at.is_none()) else {
+            return Ok(None);
+        };
+        if versions.is_some_and(|versions| !versions.contains(&link.version)) {
+            return Ok(None);
+        }
+        if let Some(url) = changes.url {
+            link.url = url.to_string();
+        }
+        if let Some(expires_at) = changes.expires_at {
+            link.expires_at = expires_at;
+        }
+        if let Some(max_clicks) = changes.max_clicks {
+            link.max_clicks = max_clicks;
+            link.remaining_clicks = max_clicks;
+        }
+        if let Some(redirect_type) = changes.redirect_type {
+            link.redirect_type = redirect_type;
+        }
+        if let Some(forward_path) = changes.forward_path {
+            link.forward_path = forward_path;
+        }
+        if let Some(forward_query) = changes.forward_query {
+            link.forward_query = forward_query;
+        }
+        if let Some(password_hash) = changes.password_hash {
+            link.password_hash = password_hash.map(str::to_string);
+        }
+        link.version += 1;
+        let updated = UpdatedLink {
+            url: link.url.clone(),
+            expires_at: link.expires_at,
+            max_clicks: link.max_clicks,
+            redirect_type: link.redirect_type,
+            forward_path: link.forward_path,
+            forward_query: link.forward_query,
+            password_protected: link.password_hash.is_some(),
+            version: link.version,
+        };
+        if changes.url.is_some() {
+            memory.insert_revision(id, &updated.url, author);
+        }
+        Ok(Some(updated))
+    }
+
+    async fn list_links(
+        &self,
+        filter: &LinkFilter<'_>,
+        sort: LinkSort,
+        descending: bool,
+        after: Option<&LinkCursor>,
+        limit: i64,
+    ) -> Result<Vec<LinkSummary>, Error> {
+        let memory = self.lock();
+        let mut links: Vec<_> = memory
+            .links
+            .values()
+            .filter(|link| matches(link, filter))
+            .map(|link| memory.summary(link))
+            .collect();
+        let direction = if descending { Ordering::Less } else { Ordering::Greater };
+        if let Some(after) = after {
+            links.retain(|link| compare_to_cursor(link, after) == direction);
+        }
+        links.sort_by(|a, b| {
+            let ordering = match sort {
+                LinkSort::CreatedAt => (a.created_at, &a.id).cmp(&(b.created_at, &b.id)),
+                LinkSort::Clicks => (a.clicks, &a.id).cmp(&(b.clicks, &b.id)),
+            };
+            if descending { ordering.reverse() } else { ordering }
+        });
+        links.truncate(usize::try_from(limit).unwrap_or_default());
+        Ok(links)
+    }
+
+    async fn list_link_revisions(&self, id: &str) -> Result<Vec<LinkRevision>, Error> {
+        let memory = self.lock();
+        let revisions = memory.revisions.get(id).map(Vec::as_slice).unwrap_or_default();
+        Ok(revisions
+            .iter()
+            .rev()
+            .map(|revision| LinkRevision {
+                revision: revision.revision,
+                url: revision.url.clone(),
+                author: revision.author,
+                author_name: memory.api_key_name(revision.author),
+                created_at: revision.created_at,
+            })
+            .collect())
+    }
+
+    async fn get_link_revision(&self, id: &str, revision: i32) -> Result<Option<String>, Error> {
+        let memory = self.lock();
+        let revisions = memory.revisions.get(id).map(Vec::as_slice).unwrap_or_default();
+        Ok(revisions
+            .iter()
+            .find(|stored| stored.revision == revision)
+            .map(|stored| stored.url.clone()))
+    }
+
+    async fn insert_clicks(&self, clicks: &[Click]) -> Result<u64, Error> {
+        let mut memory = self.lock();
+        let mut inserted = 0;
+        for click in clicks {
+            if memory.links.contains_key(&click.link_id) {
+                memory.clicks.entry(click.link_id.clone()).or_default().push(click.clone());
+                inserted += 1;
+            }
+        }
+        Ok(inserted)
+    }
+
+    async fn count_clicks(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<ClickCount, Error> {
+        let memory = self.lock();
+        let clicks: Vec<_> = memory.clicks_between(id, from, to).collect();
+        Ok(count(&clicks))
+    }
+
+    async fn click_series(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        granularity: Granularity,
+    ) -> Result<Vec<ClickBucket>, Error> {
+        let memory = self.lock();
+        let mut buckets: HashMap<_, Vec<_>> = HashMap::new();
+        for click in memory.clicks_between(id, from, to) {
+            buckets.entry(bucket(click.clicked_at, granularity)).or_default().push(click);
+        }
+        let mut series: Vec<_> = buckets
+            .into_iter()
+            .map(|(bucket, clicks)| ClickBucket { bucket, count: count(&clicks) })
+            .collect();
+        series.sort_by_key(|bucket| bucket.bucket);
+        Ok(series)
+    }
+
+    async fn top_click_values(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        dimension: ClickDimension,
+        limit: i64,
+    ) -> Result<Vec<ClickValue>, Error> {
+        let memory = self.lock();
+        let mut counts: HashMap<Option<&str>, i64> = HashMap::new();
+        for click in memory.clicks_between(id, from, to) {
+            let value = match dimension {
+                ClickDimension::Referer => &click.referer,
+                ClickDimension::UserAgent => &click.user_agent,
+                ClickDimension::Country => &click.country,
+            };
+            *counts.entry(value.as_deref()).or_default() += 1;
+        }
+        let mut values: Vec<_> = counts.into_iter().collect();
+        // Most clicks first, then by value with missing values last like Postgres sorts NULLs
+        values.sort_by(|(a, a_clicks), (b, b_clicks)| {
+            b_clicks.cmp(a_clicks).then_with(|| match (a, b) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            })
+        });
+        values.truncate(usize::try_from(limit).unwrap_or_default());
+        Ok(values
+            .into_iter()
+            .map(|(value, clicks)| ClickValue { value: value.map(str::to_string), clicks })
+            .collect())
+    }
+
+    async fn purge_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        let before = OffsetDateTime::now_utc() - retention;
+        Ok(self.lock().remove_links(|link| link.is_expired(before)).len() as u64)
+    }
+
+    async fn archive_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        let before = OffsetDateTime::now_utc() - retention;
+        let mut memory = self.lock();
+        let expired = memory.remove_links(|link| link.is_expired(before));
+        let count = expired.len() as u64;
+        memory.archive.extend(expired);
+        Ok(count)
+    }
+
+    async fn purge_deleted_links(&self, retention: Duration) -> Result<u64, Error> {
+        let before = OffsetDateTime::now_utc() - retention;
+        let purged = self
+            .lock()
+            .remove_links(|link| link.deleted_at.is_some_and(|deleted_at| deleted_at <= before));
+        Ok(purged.len() as u64)
+    }
+}
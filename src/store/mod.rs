@@ -0,0 +1,412 @@
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+pub use self::memory::MemoryStore;
+pub use self::postgres::PostgresStore;
+pub use self::sqlite::SqliteStore;
+use crate::error::Error;
+use crate::model::{
+    ApiKey, Click, ClickBucket, ClickCount, ClickDimension, ClickValue, Granularity, LinkChanges,
+    LinkCursor, LinkDetails, LinkFilter, LinkOwner, LinkRevision, LinkSort, LinkSummary, NewLink,
+    Resolved, UpdatedLink,
+};
+
+/// Columns of a [`LinkSummary`] selected from `link`, shared by the SQL stores.
+macro_rules! link_summary_columns {
+    () => {
+        "link.id, link.url, link.created_at, link.expires_at, link.max_clicks,
+        link.remaining_clicks, link.owner, link.redirect_type, link.forward_path,
+        link.forward_query, link.password_hash IS NOT NULL AS password_protected,
+        link.version, link.deleted_at,
+        (SELECT count(*) FROM click WHERE click.link_id = link.id) AS clicks"
+    };
+}
+pub(crate) use link_summary_columns;
+
+/// Storage of links, their revisions and clicks, and of API keys.
+///
+/// Implementations report a taken alias as [`Error::Conflict`] and an unreachable backend as
+/// [`Error::Unavailable`].
+#[async_trait]
+pub trait LinkStore: Send + Sync {
+    /// Checks that the backend can be reached.
+    async fn ping(&self) -> Result<(), Error>;
+
+    async fn create_api_key(&self, name: &str, key_hash: &[u8]) -> Result<i64, Error>;
+
+    /// Looks up the non-revoked API key with the given hash.
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, Error>;
+
+    /// Creates a link and records its destination as its first revision, authored by its owner.
+    /// A link with the same alias that was moved to the trash more than `quarantine` ago is
+    /// removed first.
+    async fn create_link(&self, link: &NewLink<'_>, quarantine: Duration) -> Result<(), Error>;
+
+    /// Resolves `id` for a redirect, atomically consuming one click of click-limited links.
+    /// Password-protected links are only resolved (and a click consumed) if `unlocked` is set.
+    async fn get_link(&self, id: &str, unlocked: bool) -> Result<Option<Resolved>, Error>;
+
+    /// Reads the link `id` without consuming a click.
+    async fn get_link_details(&self, id: &str) -> Result<Option<LinkDetails>, Error>;
+
+    /// Reads the link `id` as [`LinkStore::list_links`] lists it, including its version.
+    async fn get_link_summary(&self, id: &str) -> Result<Option<LinkSummary>, Error>;
+
+    /// Returns the password hash of the link `id`, `None` if the link does not exist.
+    async fn get_link_password(&self, id: &str) -> Result<Option<Option<String>>, Error>;
+
+    /// Returns the owner of the link `id`, including links in the trash.
+    async fn get_link_owner(&self, id: &str) -> Result<Option<LinkOwner>, Error>;
+
+    async fn link_exists(&self, id: &str) -> Result<bool, Error>;
+
+    /// Moves the link `id` to the trash.
+    async fn delete_link(&self, id: &str) -> Result<(), Error>;
+
+    /// Takes the link `id` out of the trash, returning its new version or `None` if it is not in
+    /// the trash.
+    async fn restore_link(&self, id: &str) -> Result<Option<i64>, Error>;
+
+    /// Applies `changes` to the link `id` if its version is one of `versions` (any version if
+    /// `None`), recording a destination differing from the latest revision as a new revision by
+    /// `author`. Returns `None` if the link does not exist or has a different version.
+    async fn update_link(
+        &self,
+        id: &str,
+        changes: &LinkChanges<'_>,
+        versions: Option<&[i64]>,
+        author: Option<i64>,
+    ) -> Result<Option<UpdatedLink>, Error>;
+
+    /// Lists up to `limit` links matching `filter` in the order of `sort`, continuing after
+    /// `after`.
+    async fn list_links(
+        &self,
+        filter: &LinkFilter<'_>,
+        sort: LinkSort,
+        descending: bool,
+        after: Option<&LinkCursor>,
+        limit: i64,
+    ) -> Result<Vec<LinkSummary>, Error>;
+
+    /// Lists the revisions of the link `id`, newest first.
+    async fn list_link_revisions(&self, id: &str) -> Result<Vec<LinkRevision>, Error>;
+
+    /// Returns the destination of revision `revision` of the link `id`.
+    async fn get_link_revision(&self, id: &str, revision: i32) -> Result<Option<String>, Error>;
+
+    /// Inserts a batch of clicks, skipping clicks of links deleted in the meantime.
+    async fn insert_clicks(&self, clicks: &[Click]) -> Result<u64, Error>;
+
+    /// Counts the clicks of `id` within `[from, to)`, visitors are told apart by IP and user agent.
+    async fn count_clicks(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<ClickCount, Error>;
+
+    /// Counts the clicks of `id` within `[from, to)` per UTC time bucket, omitting empty buckets.
+    async fn click_series(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        granularity: Granularity,
+    ) -> Result<Vec<ClickBucket>, Error>;
+
+    /// Returns the `limit` most frequent values of `dimension` among the clicks of `id` within
+    /// `[from, to)`.
+    async fn top_click_values(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        dimension: ClickDimension,
+        limit: i64,
+    ) -> Result<Vec<ClickValue>, Error>;
+
+    /// Deletes links that expired more than `retention` ago, returning how many were removed.
+    async fn purge_expired_links(&self, retention: Duration) -> Result<u64, Error>;
+
+    /// Moves links that expired more than `retention` ago into the archive along with their
+    /// revisions and clicks, returning how many were moved.
+    async fn archive_expired_links(&self, retention: Duration) -> Result<u64, Error>;
+
+    /// Removes links moved to the trash more than `retention` ago, returning how many were removed.
+    async fn purge_deleted_links(&self, retention: Duration) -> Result<u64, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::destination::QueryForwarding;
+
+    const QUARANTINE: Duration = Duration::from_secs(3600);
+
+    fn new_link(id: &str) -> NewLink<'_> {
+        NewLink {
+            id,
+            url: "https://example.com/",
+            expires_at: None,
+            max_clicks: None,
+            owner: None,
+            redirect_type: None,
+            forward_path: false,
+            forward_query: QueryForwarding::Off,
+            password_hash: None,
+        }
+    }
+
+    fn click(id: &str, clicked_at: OffsetDateTime, ip: u8, referer: &str) -> Click {
+        Click {
+            link_id: id.to_string(),
+            clicked_at,
+            referer: Some(referer.to_string()),
+            user_agent: Some("test".to_string()),
+            client_ip: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, ip))),
+            accept_language: None,
+            country: None,
+        }
+    }
+
+    fn is_active(resolved: &Option<Resolved>) -> bool {
+        matches!(resolved, Some(Resolved::Active(_)))
+    }
+
+    async fn list_all(
+        store: &dyn LinkStore,
+        filter: &LinkFilter<'_>,
+        sort: LinkSort,
+        descending: bool,
+        limit: i64,
+    ) -> Vec<String> {
+        let mut ids = Vec::new();
+        let mut after = None;
+        loop {
+            let page =
+                store.list_links(filter, sort, descending, after.as_ref(), limit).await.unwrap();
+            let Some(last) = page.last() else {
+                return ids;
+            };
+            after = Some(last.cursor(sort));
+            ids.extend(page.into_iter().map(|link| link.id));
+        }
+    }
+
+    async fn consumes_each_click_once(store: Arc<dyn LinkStore>) {
+        let link = NewLink { max_clicks: Some(3), ..new_link("a") };
+        store.create_link(&link, QUARANTINE).await.unwrap();
+        let redirects: Vec<_> = (0..10)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.get_link("a", false).await.unwrap() })
+            })
+            .collect();
+        let mut active = 0;
+        for redirect in redirects {
+            match redirect.await.unwrap() {
+                Some(Resolved::Active(_)) => active += 1,
+                Some(Resolved::Exhausted) => {}
+                resolved => panic!("Unexpected {resolved:?}"),
+            }
+        }
+        assert_eq!(active, 3);
+        assert_eq!(store.get_link_details("a").await.unwrap().unwrap().remaining_clicks, Some(0));
+
+        let link = NewLink { max_clicks: Some(1), password_hash: Some("hash"), ..new_link("b") };
+        store.create_link(&link, QUARANTINE).await.unwrap();
+        assert!(matches!(store.get_link("b", false).await.unwrap(), Some(Resolved::Locked)));
+        assert!(is_active(&store.get_link("b", true).await.unwrap()));
+        assert!(matches!(store.get_link("b", true).await.unwrap(), Some(Resolved::Exhausted)));
+    }
+
+    async fn expires_links(store: Arc<dyn LinkStore>) {
+        let expires_at = OffsetDateTime::now_utc() - time::Duration::minutes(1);
+        let link = NewLink { expires_at: Some(expires_at), max_clicks: Some(1), ..new_link("a") };
+        store.create_link(&link, QUARANTINE).await.unwrap();
+        store.create_link(&new_link("b"), QUARANTINE).await.unwrap();
+
+        assert!(matches!(store.get_link("a", false).await.unwrap(), Some(Resolved::Expired)));
+        assert_eq!(store.get_link_details("a").await.unwrap().unwrap().remaining_clicks, Some(1));
+        assert_eq!(store.purge_expired_links(Duration::from_secs(3600)).await.unwrap(), 0);
+        assert_eq!(store.purge_expired_links(Duration::ZERO).await.unwrap(), 1);
+        assert!(store.get_link("a", false).await.unwrap().is_none());
+        assert!(is_active(&store.get_link("b", false).await.unwrap()));
+
+        let link = NewLink { expires_at: Some(expires_at), ..new_link("c") };
+        store.create_link(&link, QUARANTINE).await.unwrap();
+        assert_eq!(store.archive_expired_links(Duration::ZERO).await.unwrap(), 1);
+        assert!(store.get_link_details("c").await.unwrap().is_none());
+        assert!(store.list_link_revisions("c").await.unwrap().is_empty());
+    }
+
+    async fn pages_with_keyset_cursors(store: Arc<dyn LinkStore>) {
+        for id in ["a", "b", "c", "d", "e"] {
+            store.create_link(&new_link(id), QUARANTINE).await.unwrap();
+        }
+        let now = OffsetDateTime::now_utc();
+        let clicks = [("a", 1), ("c", 1), ("e", 2)]
+            .into_iter()
+            .flat_map(|(id, count)| (0..count).map(move |ip| click(id, now, ip, "")))
+            .collect::<Vec<_>>();
+        store.insert_clicks(&clicks).await.unwrap();
+
+        let filter = LinkFilter::default();
+        for limit in [1, 2, 10] {
+            let ids = list_all(&*store, &filter, LinkSort::CreatedAt, false, limit).await;
+            assert_eq!(ids, ["a", "b", "c", "d", "e"]);
+            let ids = list_all(&*store, &filter, LinkSort::CreatedAt, true, limit).await;
+            assert_eq!(ids, ["e", "d", "c", "b", "a"]);
+            // Ties on the click count are broken by the alias, also across page boundaries
+            let ids = list_all(&*store, &filter, LinkSort::Clicks, false, limit).await;
+            assert_eq!(ids, ["b", "d", "a", "c", "e"]);
+            let ids = list_all(&*store, &filter, LinkSort::Clicks, true, limit).await;
+            assert_eq!(ids, ["e", "c", "a", "d", "b"]);
+        }
+
+        let filter = LinkFilter { prefix: Some("c"), ..LinkFilter::default() };
+        assert_eq!(list_all(&*store, &filter, LinkSort::CreatedAt, false, 10).await, ["c"]);
+    }
+
+    async fn bumps_versions_and_records_revisions(store: Arc<dyn LinkStore>) {
+        let owner = store.create_api_key("owner", b"owner").await.unwrap();
+        let editor = store.create_api_key("editor", b"editor").await.unwrap();
+        let link = NewLink { owner: Some(owner), ..new_link("a") };
+        store.create_link(&link, QUARANTINE).await.unwrap();
+
+        let changes = LinkChanges { url: Some("https://example.com/new"), ..Default::default() };
+        let updated = store.update_link("a", &changes, Some(&[1]), Some(editor)).await.unwrap();
+        assert_eq!(updated.unwrap().version, 2);
+        assert!(store.update_link("a", &changes, Some(&[1]), None).await.unwrap().is_none());
+        // Keeping the destination bumps the version without recording a revision
+        let updated = store.update_link("a", &changes, None, None).await.unwrap();
+        assert_eq!(updated.unwrap().version, 3);
+
+        let revisions = store.list_link_revisions("a").await.unwrap();
+        let revisions: Vec<_> = revisions
+            .iter()
+            .map(|rev| (rev.revision, rev.url.as_str(), rev.author))
+            .collect();
+        assert_eq!(
+            revisions,
+            [
+                (2, "https://example.com/new", Some(editor)),
+                (1, "https://example.com/", Some(owner))
+            ]
+        );
+        let url = store.get_link_revision("a", 1).await.unwrap();
+        assert_eq!(url.as_deref(), Some("https://example.com/"));
+        assert!(store.get_link_revision("a", 3).await.unwrap().is_none());
+
+        assert_eq!(store.get_link_summary("a").await.unwrap().unwrap().version, 3);
+
+        store.delete_link("a").await.unwrap();
+        assert!(store.get_link_summary("a").await.unwrap().is_none());
+        assert_eq!(store.restore_link("a").await.unwrap(), Some(4));
+    }
+
+    async fn quarantines_deleted_aliases(store: Arc<dyn LinkStore>) {
+        store.create_link(&new_link("a"), QUARANTINE).await.unwrap();
+        store.delete_link("a").await.unwrap();
+        assert!(store.get_link("a", false).await.unwrap().is_none());
+        assert!(store.get_link_owner("a").await.unwrap().unwrap().deleted);
+        let trash = LinkFilter { deleted: true, ..LinkFilter::default() };
+        assert_eq!(list_all(&*store, &trash, LinkSort::CreatedAt, false, 10).await, ["a"]);
+        assert!(
+            list_all(&*store, &LinkFilter::default(), LinkSort::CreatedAt, false, 10)
+                .await
+                .is_empty()
+        );
+
+        let result = store.create_link(&new_link("a"), QUARANTINE).await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+        assert_eq!(store.restore_link("a").await.unwrap(), Some(2));
+        assert_eq!(store.restore_link("a").await.unwrap(), None);
+        assert!(is_active(&store.get_link("a", false).await.unwrap()));
+
+        store.delete_link("a").await.unwrap();
+        assert_eq!(store.purge_deleted_links(QUARANTINE).await.unwrap(), 0);
+        store.create_link(&new_link("a"), Duration::ZERO).await.unwrap();
+        assert!(!store.get_link_owner("a").await.unwrap().unwrap().deleted);
+        assert_eq!(store.list_link_revisions("a").await.unwrap().len(), 1);
+    }
+
+    async fn buckets_clicks(store: Arc<dyn LinkStore>) {
+        store.create_link(&new_link("a"), QUARANTINE).await.unwrap();
+        let day =
+            OffsetDateTime::now_utc().replace_time(time::Time::MIDNIGHT) - time::Duration::days(1);
+        let minutes = |minutes| day + time::Duration::minutes(minutes);
+        let clicks = [
+            click("a", minutes(10), 1, "https://one.example/"),
+            click("a", minutes(20), 1, "https://one.example/"),
+            click("a", minutes(70), 2, "https://two.example/"),
+            click("gone", minutes(10), 1, ""),
+        ];
+        assert_eq!(store.insert_clicks(&clicks).await.unwrap(), 3);
+
+        let (from, to) = (day, day + time::Duration::days(1));
+        let count = store.count_clicks("a", from, to).await.unwrap();
+        assert_eq!((count.clicks, count.unique_visitors), (3, 2));
+        let count = store.count_clicks("a", minutes(60), to).await.unwrap();
+        assert_eq!((count.clicks, count.unique_visitors), (1, 1));
+
+        let series = store.click_series("a", from, to, Granularity::Hour).await.unwrap();
+        let series: Vec<_> = series
+            .iter()
+            .map(|bucket| (bucket.bucket, bucket.count.clicks, bucket.count.unique_visitors))
+            .collect();
+        assert_eq!(series, [(day, 2, 1), (minutes(60), 1, 1)]);
+        let series = store.click_series("a", from, to, Granularity::Day).await.unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!((series[0].bucket, series[0].count.clicks), (day, 3));
+
+        let top = store.top_click_values("a", from, to, ClickDimension::Referer, 1).await.unwrap();
+        let top: Vec<_> = top.iter().map(|value| (value.value.as_deref(), value.clicks)).collect();
+        assert_eq!(top, [(Some("https://one.example/"), 2)]);
+    }
+
+    // Runs every check above against a fresh store of each backend
+    macro_rules! conformance {
+        ($($check:ident),* $(,)?) => {
+            mod memory {
+                $(
+                    #[tokio::test]
+                    async fn $check() {
+                        super::$check(std::sync::Arc::new(super::MemoryStore::new())).await;
+                    }
+                )*
+            }
+
+            mod sqlite {
+                $(
+                    #[tokio::test]
+                    async fn $check() {
+                        let dir = tempfile::tempdir().unwrap();
+                        let path = dir.path().join("links.db");
+                        let store = super::SqliteStore::open(path, super::Duration::from_secs(5));
+                        super::$check(std::sync::Arc::new(store.unwrap())).await;
+                    }
+                )*
+            }
+        };
+    }
+
+    conformance!(
+        consumes_each_click_once,
+        expires_links,
+        pages_with_keyset_cursors,
+        bumps_versions_and_records_revisions,
+        quarantines_deleted_aliases,
+        buckets_clicks,
+    );
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+use super::LinkStore;
+use crate::database;
+use crate::error::Error;
+use crate::model::{
+    ApiKey, Click, ClickBucket, ClickCount, ClickDimension, ClickValue, Granularity, LinkChanges,
+    LinkCursor, LinkDetails, LinkFilter, LinkOwner, LinkRevision, LinkSort, LinkSummary, NewLink,
+    Resolved, UpdatedLink,
+};
+
+/// Link store backed by the Postgres schema of `database/init.sql`.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: deadpool_postgres::Pool,
+}
+
+impl PostgresStore {
+    #[must_use]
+    pub fn new(pool: deadpool_postgres::Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Client, Error> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+#[async_trait]
+impl LinkStore for PostgresStore {
+    async fn ping(&self) -> Result<(), Error> {
+        let client = self.client().await?;
+        database::ping(&client).await.map_err(|err| Error::Unavailable(err.into()))
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &[u8]) -> Result<i64, Error> {
+        let client = self.client().await?;
+        Ok(database::create_api_key(&client, name, key_hash).await?)
+    }
+
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, Error> {
+        let client = self.client().await?;
+        Ok(database::find_api_key(&client, key_hash).await?)
+    }
+
+    async fn create_link(&self, link: &NewLink<'_>, quarantine: Duration) -> Result<(), Error> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        database::release_deleted_link(&transaction, link.id, quarantine.as_secs_f64()).await?;
+        database::create_link(&transaction, link).await?;
+        database::insert_link_revision(&transaction, link.id, link.url, link.owner).await?;
+        Ok(transaction.commit().await?)
+    }
+
+    async fn get_link(&self, id: &str, unlocked: bool) -> Result<Option<Resolved>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link(&client, id, unlocked).await?)
+    }
+
+    async fn get_link_details(&self, id: &str) -> Result<Option<LinkDetails>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link_details(&client, id).await?)
+    }
+
+    async fn get_link_summary(&self, id: &str) -> Result<Option<LinkSummary>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link_summary(&client, id).await?)
+    }
+
+    async fn get_link_password(&self, id: &str) -> Result<Option<Option<String>>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link_password(&client, id).await?)
+    }
+
+    async fn get_link_owner(&self, id: &str) -> Result<Option<LinkOwner>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link_owner(&client, id).await?)
+    }
+
+    async fn link_exists(&self, id: &str) -> Result<bool, Error> {
+        let client = self.client().await?;
+        Ok(database::link_exists(&client, id).await?)
+    }
+
+    async fn delete_link(&self, id: &str) -> Result<(), Error> {
+        let client = self.client().await?;
+        Ok(database::delete_link(&client, id).await?)
+    }
+
+    async fn restore_link(&self, id: &str) -> Result<Option<i64>, Error> {
+        let client = self.client().await?;
+        Ok(database::restore_link(&client, id).await?)
+    }
+
+    async fn update_link(
+        &self,
+        id: &str,
+        changes: &LinkChanges<'_>,
+        versions: Option<&[i64]>,
+        author: Option<i64>,
+    ) -> Result<Option<UpdatedLink>, Error> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        let Some(link) = database::update_link(&transaction, id, changes, versions).await? else {
+            return Ok(None);
+        };
+        if changes.url.is_some() {
+            database::insert_link_revision(&transaction, id, &link.url, author).await?;
+        }
+        transaction.commit().await?;
+        Ok(Some(link))
+    }
+
+    async fn list_links(
+        &self,
+        filter: &LinkFilter<'_>,
+        sort: LinkSort,
+        descending: bool,
+        after: Option<&LinkCursor>,
+        limit: i64,
+    ) -> Result<Vec<LinkSummary>, Error> {
+        let client = self.client().await?;
+        Ok(database::list_links(&client, filter, sort, descending, after, limit).await?)
+    }
+
+    async fn list_link_revisions(&self, id: &str) -> Result<Vec<LinkRevision>, Error> {
+        let client = self.client().await?;
+        Ok(database::list_link_revisions(&client, id).await?)
+    }
+
+    async fn get_link_revision(&self, id: &str, revision: i32) -> Result<Option<String>, Error> {
+        let client = self.client().await?;
+        Ok(database::get_link_revision(&client, id, revision).await?)
+    }
+
+    async fn insert_clicks(&self, clicks: &[Click]) -> Result<u64, Error> {
+        let client = self.client().await?;
+        Ok(database::insert_clicks(&client, clicks).await?)
+    }
+
+    async fn count_clicks(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<ClickCount, Error> {
+        let client = self.client().await?;
+        Ok(database::count_clicks(&client, id, from, to).await?)
+    }
+
+    async fn click_series(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        granularity: Granularity,
+    ) -> Result<Vec<ClickBucket>, Error> {
+        let client = self.client().await?;
+        Ok(database::click_series(&client, id, from, to, granularity).await?)
+    }
+
+    async fn top_click_values(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        dimension: ClickDimension,
+        limit: i64,
+    ) -> Result<Vec<ClickValue>, Error> {
+        let client = self.client().await?;
+        Ok(database::top_click_values(&client, id, from, to, dimension, limit).await?)
+    }
+
+    async fn purge_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        let client = self.client().await?;
+        Ok(database::purge_expired_links(&client, retention.as_secs_f64()).await?)
+    }
+
+    async fn archive_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        let client = self.client().await?;
+        Ok(database::archive_expired_links(&client, retention.as_secs_f64()).await?)
+    }
+
+    async fn purge_deleted_links(&self, retention: Duration) -> Result<u64, Error> {
+        let client = self.client().await?;
+        Ok(database::purge_deleted_links(&client, retention.as_secs_f64()).await?)
+    }
+}
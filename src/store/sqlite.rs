@@ -0,0 +1,776 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{Connection, OptionalExtension, Row, ToSql, TransactionBehavior, params};
+use time::OffsetDateTime;
+
+use super::{LinkStore, link_summary_columns};
+use crate::destination::QueryForwarding;
+use crate::error::Error;
+use crate::model::{
+    ApiKey, Click, ClickBucket, ClickCount, ClickDimension, ClickValue, Granularity, LinkChanges,
+    LinkCursor, LinkDetails, LinkFilter, LinkOwner, LinkRevision, LinkSort, LinkSummary, NewLink,
+    RedirectTarget, RedirectType, Resolved, UpdatedLink,
+};
+
+const SCHEMA: &str = include_str!("../../database/sqlite.sql");
+
+/// Link store in a single SQLite file, for deployments without a Postgres server.
+///
+/// Queries run on the blocking thread pool. The file is switched to WAL mode so redirects keep
+/// reading while links are written.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: r2d2::Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStore {
+    /// Opens or creates the database at `path` and creates missing tables. `timeout` bounds the
+    /// wait for a pooled connection and for locks held by other connections.
+    pub fn open(path: impl AsRef<Path>, timeout: Duration) -> anyhow::Result<Self> {
+        let manager = SqliteConnectionManager::file(path).with_init(move |conn| {
+            conn.busy_timeout(timeout)?;
+            conn.pragma_update(None, "foreign_keys", true)?;
+            conn.pragma_update(None, "synchronous", "NORMAL")?;
+            conn.create_scalar_function("url_host", 1, FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+                let url = ctx.get::<Option<String>>(0)?;
+                Ok(url
+                    .and_then(|url| url::Url::parse(&url).ok()?.host_str().map(str::to_lowercase)))
+            })
+        });
+        let pool = r2d2::Pool::builder()
+            .connection_timeout(timeout)
+            .build(manager)
+            .context("Create SQLite connection pool")?;
+        let conn = pool.get()?;
+        let mode: String =
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            tracing::warn!("SQLite database does not support WAL mode, using {mode}");
+        }
+        conn.execute_batch(SCHEMA).context("Create SQLite schema")?;
+        Ok(Self { pool })
+    }
+
+    // Run `f` with a pooled connection on the blocking thread pool
+    async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            Ok(f(&mut conn)?)
+        })
+        .await
+        .map_err(|err| Error::Internal(format!("SQLite task failed: {err}")))?
+    }
+}
+
+/// Timestamp stored as microseconds since the Unix epoch.
+struct Timestamp(OffsetDateTime);
+
+impl FromSql for Timestamp {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let micros = value.as_i64()?;
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(micros) * 1_000)
+            .map(Self)
+            .map_err(|err| FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl ToSql for Timestamp {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from((self.0.unix_timestamp_nanos() / 1_000) as i64))
+    }
+}
+
+fn timestamp(row: &Row<'_>, column: &str) -> rusqlite::Result<OffsetDateTime> {
+    Ok(row.get::<_, Timestamp>(column)?.0)
+}
+
+fn optional_timestamp(row: &Row<'_>, column: &str) -> rusqlite::Result<Option<OffsetDateTime>> {
+    Ok(row.get::<_, Option<Timestamp>>(column)?.map(|timestamp| timestamp.0))
+}
+
+fn redirect_type(row: &Row<'_>) -> rusqlite::Result<Option<RedirectType>> {
+    let code: Option<u16> = row.get("redirect_type")?;
+    Ok(code.and_then(|code| RedirectType::try_from(code).ok()))
+}
+
+fn forward_query(row: &Row<'_>) -> rusqlite::Result<QueryForwarding> {
+    Ok(QueryForwarding::from_str_lossy(&row.get::<_, String>("forward_query")?))
+}
+
+fn link_summary(row: &Row<'_>) -> rusqlite::Result<LinkSummary> {
+    Ok(LinkSummary {
+        id: row.get("id")?,
+        url: row.get("url")?,
+        created_at: timestamp(row, "created_at")?,
+        expires_at: optional_timestamp(row, "expires_at")?,
+        max_clicks: row.get("max_clicks")?,
+        remaining_clicks: row.get("remaining_clicks")?,
+        owner: row.get("owner")?,
+        redirect_type: redirect_type(row)?,
+        forward_path: row.get("forward_path")?,
+        forward_query: forward_query(row)?,
+        password_protected: row.get("password_protected")?,
+        version: row.get("version")?,
+        deleted_at: optional_timestamp(row, "deleted_at")?,
+        clicks: row.get("clicks")?,
+    })
+}
+
+fn insert_link_revision(
+    conn: &Connection,
+    id: &str,
+    url: &str,
+    author: Option<i64>,
+) -> rusqlite::Result<Option<i32>> {
+    const LATEST_SQL: &str =
+        "SELECT revision, url FROM link_revision WHERE link_id = ?1 ORDER BY revision DESC LIMIT 1";
+    const INSERT_SQL: &str =
+        "INSERT INTO link_revision (link_id, revision, url, author, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)";
+
+    let latest = conn
+        .query_row(LATEST_SQL, [id], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?)))
+        .optional()?;
+    let revision = match latest {
+        Some((_, latest_url)) if latest_url == url => return Ok(None),
+        Some((revision, _)) => revision + 1,
+        None => 1,
+    };
+    let now = Timestamp(OffsetDateTime::now_utc());
+    conn.execute(INSERT_SQL, params![id, revision, url, author, now])?;
+    Ok(Some(revision))
+}
+
+/// Links matching the filter, followed by the condition continuing after the cursor.
+const LIST_LINKS_SQL: &str = concat!(
+    "SELECT * FROM (
+        SELECT ",
+    link_summary_columns!(),
+    "
+        FROM link
+        WHERE (?1 IS NULL OR url_host(link.url) = lower(?1))
+          AND (?2 IS NULL OR link.owner = ?2)
+          AND (?3 IS NULL OR link.created_at >= ?3)
+          AND (?4 IS NULL OR link.created_at < ?4)
+          AND (?5 IS NULL OR substr(link.id, 1, length(?5)) = ?5)
+          AND (link.deleted_at IS NOT NULL) = ?10
+    ) AS l
+    WHERE ?8 IS NULL OR "
+);
+
+/// Clicks within `[?2, ?3)` of the link `?1`, visitors are told apart by IP and user agent.
+const CLICK_COUNTS: &str = "count(*) AS clicks,
+    count(DISTINCT quote(client_ip) || ',' || quote(user_agent)) AS unique_visitors
+    FROM click WHERE link_id = ?1 AND clicked_at >= ?2 AND clicked_at < ?3";
+
+#[async_trait]
+impl LinkStore for SqliteStore {
+    async fn ping(&self) -> Result<(), Error> {
+        self.run(|conn| conn.query_row("SELECT 1", [], |_| Ok(()))).await
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &[u8]) -> Result<i64, Error> {
+        const SQL: &str = "INSERT INTO api_key (name, key_hash) VALUES (?1, ?2) RETURNING id";
+
+        let (name, key_hash) = (name.to_string(), key_hash.to_vec());
+        self.run(move |conn| conn.query_row(SQL, params![name, key_hash], |row| row.get("id")))
+            .await
+    }
+
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, Error> {
+        const SQL: &str = "SELECT id, name FROM api_key WHERE key_hash = ?1 AND revoked_at IS NULL";
+
+        let key_hash = key_hash.to_vec();
+        self.run(move |conn| {
+            conn.query_row(SQL, [key_hash], |row| {
+                Ok(ApiKey { id: row.get("id")?, name: row.get("name")? })
+            })
+            .optional()
+        })
+        .await
+    }
+
+    async fn create_link(&self, link: &NewLink<'_>, quarantine: Duration) -> Result<(), Error> {
+        const RELEASE_SQL: &str = "DELETE FROM link WHERE id = ?1 AND deleted_at <= ?2";
+        const INSERT_SQL: &str = "INSERT INTO link
+                (id, url, expires_at, max_clicks, remaining_clicks, owner, redirect_type,
+                 forward_path, forward_query, password_hash, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+
+        let (id, url) = (link.id.to_string(), link.url.to_string());
+        let password_hash = link.password_hash.map(str::to_string);
+        let &NewLink {
+            expires_at,
+            max_clicks,
+            owner,
+            redirect_type,
+            forward_path,
+            forward_query,
+            ..
+        } = link;
+        self.run(move |conn| {
+            let now = OffsetDateTime::now_utc();
+            let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transaction.execute(RELEASE_SQL, params![id, Timestamp(now - quarantine)])?;
+            transaction.execute(
+                INSERT_SQL,
+                params![
+                    id,
+                    url,
+                    expires_at.map(Timestamp),
+                    max_clicks,
+                    owner,
+                    redirect_type.map(RedirectType::code),
+                    forward_path,
+                    forward_query.as_str(),
+                    password_hash,
+                    Timestamp(now),
+                ],
+            )?;
+            insert_link_revision(&transaction, &id, &url, owner)?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    async fn get_link(&self, id: &str, unlocked: bool) -> Result<Option<Resolved>, Error> {
+        const SELECT_SQL: &str = "SELECT url, redirect_type, expires_at,
+                max_clicks IS NOT NULL AS limited, remaining_clicks, forward_path, forward_query,
+                password_hash IS NOT NULL AS password_protected
+            FROM link WHERE id = ?1 AND deleted_at IS NULL";
+        // Guarded so that concurrent redirects cannot exceed `max_clicks`
+        const CONSUME_SQL: &str = "UPDATE link SET remaining_clicks = remaining_clicks - 1
+            WHERE id = ?1 AND deleted_at IS NULL AND remaining_clicks > 0
+              AND (expires_at IS NULL OR expires_at > ?2) AND (password_hash IS NULL OR ?3)";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            let link = conn
+                .query_row(SELECT_SQL, [&id], |row| {
+                    let target = RedirectTarget {
+                        url: row.get("url")?,
+                        redirect_type: redirect_type(row)?,
+                        expires_at: optional_timestamp(row, "expires_at")?,
+                        limited: row.get("limited")?,
+                        forward_path: row.get("forward_path")?,
+                        forward_query: forward_query(row)?,
+                        password_protected: row.get("password_protected")?,
+                    };
+                    Ok((target, row.get::<_, Option<i32>>("remaining_clicks")?))
+                })
+                .optional()?;
+            let Some((target, remaining)) = link else {
+                return Ok(None);
+            };
+            let now = OffsetDateTime::now_utc();
+            let expired = target.expires_at.is_some_and(|expires_at| expires_at <= now);
+            let locked = target.password_protected && !unlocked;
+            let consumed = match remaining {
+                Some(remaining) if remaining > 0 && !expired && !locked => {
+                    conn.execute(CONSUME_SQL, params![id, Timestamp(now), unlocked])? > 0
+                }
+                _ => false,
+            };
+            if expired {
+                return Ok(Some(Resolved::Expired));
+            }
+            if remaining == Some(0) || (remaining.is_some() && !consumed && !locked) {
+                return Ok(Some(Resolved::Exhausted));
+            }
+            if locked {
+                return Ok(Some(Resolved::Locked));
+            }
+            Ok(Some(Resolved::Active(target)))
+        })
+        .await
+    }
+
+    async fn get_link_details(&self, id: &str) -> Result<Option<LinkDetails>, Error> {
+        const SQL: &str = "SELECT link.id, link.url, link.created_at, link.expires_at,
+                link.max_clicks, link.remaining_clicks, link.owner, api_key.name AS owner_name,
+                link.redirect_type, link.forward_path, link.forward_query,
+                link.password_hash IS NOT NULL AS password_protected,
+                (SELECT count(*) FROM click WHERE click.link_id = link.id) AS clicks
+            FROM link LEFT JOIN api_key ON api_key.id = link.owner
+            WHERE link.id = ?1 AND link.deleted_at IS NULL";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.query_row(SQL, [id], |row| {
+                Ok(LinkDetails {
+                    id: row.get("id")?,
+                    url: row.get("url")?,
+                    created_at: timestamp(row, "created_at")?,
+                    expires_at: optional_timestamp(row, "expires_at")?,
+                    max_clicks: row.get("max_clicks")?,
+                    remaining_clicks: row.get("remaining_clicks")?,
+                    owner: row.get("owner")?,
+                    owner_name: row.get("owner_name")?,
+                    redirect_type: redirect_type(row)?,
+                    forward_path: row.get("forward_path")?,
+                    forward_query: forward_query(row)?,
+                    password_protected: row.get("password_protected")?,
+                    clicks: row.get("clicks")?,
+                })
+            })
+            .optional()
+        })
+        .await
+    }
+
+    async fn get_link_summary(&self, id: &str) -> Result<Option<LinkSummary>, Error> {
+        const SQL: &str = concat!(
+            "SELECT ",
+            link_summary_columns!(),
+            " FROM link WHERE link.id = ?1 AND link.deleted_at IS NULL"
+        );
+
+        let id = id.to_string();
+        self.run(move |conn| conn.query_row(SQL, [id], link_summary).optional()).await
+    }
+
+    async fn get_link_password(&self, id: &str) -> Result<Option<Option<String>>, Error> {
+        const SQL: &str = "SELECT password_hash FROM link WHERE id = ?1 AND deleted_at IS NULL";
+
+        let id = id.to_string();
+        self.run(move |conn| conn.query_row(SQL, [id], |row| row.get("password_hash")).optional())
+            .await
+    }
+
+    async fn get_link_owner(&self, id: &str) -> Result<Option<LinkOwner>, Error> {
+        const SQL: &str = "SELECT owner, deleted_at IS NOT NULL AS deleted FROM link WHERE id = ?1";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.query_row(SQL, [id], |row| {
+                Ok(LinkOwner { owner: row.get("owner")?, deleted: row.get("deleted")? })
+            })
+            .optional()
+        })
+        .await
+    }
+
+    async fn link_exists(&self, id: &str) -> Result<bool, Error> {
+        const SQL: &str = "SELECT EXISTS (SELECT 1 FROM link WHERE id = ?1 AND deleted_at IS NULL)";
+
+        let id = id.to_string();
+        self.run(move |conn| conn.query_row(SQL, [id], |row| row.get(0))).await
+    }
+
+    async fn delete_link(&self, id: &str) -> Result<(), Error> {
+        const SQL: &str = "UPDATE link SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.execute(SQL, params![id, Timestamp(OffsetDateTime::now_utc())])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn restore_link(&self, id: &str) -> Result<Option<i64>, Error> {
+        const SQL: &str = "UPDATE link SET deleted_at = NULL, version = version + 1
+            WHERE id = ?1 AND deleted_at IS NOT NULL RETURNING version";
+
+        let id = id.to_string();
+        self.run(move |conn| conn.query_row(SQL, [id], |row| row.get("version")).optional())
+            .await
+    }
+
+    async fn update_link(
+        &self,
+        id: &str,
+        changes: &LinkChanges<'_>,
+        versions: Option<&[i64]>,
+        author: Option<i64>,
+    ) -> Result<Option<UpdatedLink>, Error> {
+        const SQL: &str = "UPDATE link SET
+                url = coalesce(?2, url),
+                expires_at = CASE WHEN ?3 THEN ?4 ELSE expires_at END,
+                max_clicks = CASE WHEN ?5 THEN ?6 ELSE max_clicks END,
+                remaining_clicks = CASE WHEN ?5 THEN ?6 ELSE remaining_clicks END,
+                redirect_type = CASE WHEN ?8 THEN ?9 ELSE redirect_type END,
+                forward_path = coalesce(?10, forward_path),
+                forward_query = coalesce(?11, forward_query),
+                password_hash = CASE WHEN ?12 THEN ?13 ELSE password_hash END,
+                version = version + 1
+            WHERE id = ?1 AND deleted_at IS NULL
+              AND (?7 IS NULL OR version IN (SELECT value FROM json_each(?7)))
+            RETURNING url, expires_at, max_clicks, redirect_type, forward_path, forward_query,
+                      password_hash IS NOT NULL AS password_protected, version";
+
+        let id = id.to_string();
+        let url = changes.url.map(str::to_string);
+        let password_hash = changes.password_hash.map(|hash| hash.map(str::to_string));
+        let &LinkChanges {
+            expires_at,
+            max_clicks,
+            redirect_type: new_redirect_type,
+            forward_path,
+            forward_query: new_forward_query,
+            ..
+        } = changes;
+        let versions = versions.map(|versions| serde_json::Value::from(versions).to_string());
+        self.run(move |conn| {
+            let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let link = transaction
+                .query_row(
+                    SQL,
+                    params![
+                        id,
+                        url,
+                        expires_at.is_some(),
+                        expires_at.flatten().map(Timestamp),
+                        max_clicks.is_some(),
+                        max_clicks.flatten(),
+                        versions,
+                        new_redirect_type.is_some(),
+                        new_redirect_type.flatten().map(RedirectType::code),
+                        forward_path,
+                        new_forward_query.map(QueryForwarding::as_str),
+                        password_hash.is_some(),
+                        password_hash.flatten(),
+                    ],
+                    |row| {
+                        Ok(UpdatedLink {
+                            url: row.get("url")?,
+                            expires_at: optional_timestamp(row, "expires_at")?,
+                            max_clicks: row.get("max_clicks")?,
+                            redirect_type: redirect_type(row)?,
+                            forward_path: row.get("forward_path")?,
+                            forward_query: forward_query(row)?,
+                            password_protected: row.get("password_protected")?,
+                            version: row.get("version")?,
+                        })
+                    },
+                )
+                .optional()?;
+            let Some(link) = link else {
+                return Ok(None);
+            };
+            if url.is_some() {
+                insert_link_revision(&transaction, &id, &link.url, author)?;
+            }
+            transaction.commit()?;
+            Ok(Some(link))
+        })
+        .await
+    }
+
+    async fn list_links(
+        &self,
+        filter: &LinkFilter<'_>,
+        sort: LinkSort,
+        descending: bool,
+        after: Option<&LinkCursor>,
+        limit: i64,
+    ) -> Result<Vec<LinkSummary>, Error> {
+        let (after_sql, order) = match (sort, descending) {
+            (LinkSort::CreatedAt, false) => {
+                ("(l.created_at, l.id) > (?6, ?8)", "l.created_at, l.id")
+            }
+            (LinkSort::CreatedAt, true) => {
+                ("(l.created_at, l.id) < (?6, ?8)", "l.created_at DESC, l.id DESC")
+            }
+            (LinkSort::Clicks, false) => ("(l.clicks, l.id) > (?7, ?8)", "l.clicks, l.id"),
+            (LinkSort::Clicks, true) => ("(l.clicks, l.id) < (?7, ?8)", "l.clicks DESC, l.id DESC"),
+        };
+        let sql = format!("{LIST_LINKS_SQL}{after_sql} ORDER BY {order} LIMIT ?9");
+        let (after_created_at, after_clicks, after_id) = match after {
+            Some(LinkCursor::CreatedAt(created_at, id)) => {
+                (Some(*created_at), None, Some(id.clone()))
+            }
+            Some(LinkCursor::Clicks(clicks, id)) => (None, Some(*clicks), Some(id.clone())),
+            None => (None, None, None),
+        };
+        let host = filter.host.map(str::to_string);
+        let prefix = filter.prefix.map(str::to_string);
+        let &LinkFilter { owner, created_after, created_before, deleted, .. } = filter;
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(
+                params![
+                    host,
+                    owner,
+                    created_after.map(Timestamp),
+                    created_before.map(Timestamp),
+                    prefix,
+                    after_created_at.map(Timestamp),
+                    after_clicks,
+                    after_id,
+                    limit,
+                    deleted,
+                ],
+                link_summary,
+            )?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn list_link_revisions(&self, id: &str) -> Result<Vec<LinkRevision>, Error> {
+        const SQL: &str = "SELECT r.revision, r.url, r.author, api_key.name AS author_name,
+                r.created_at
+            FROM link_revision AS r LEFT JOIN api_key ON api_key.id = r.author
+            WHERE r.link_id = ?1 ORDER BY r.revision DESC";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(SQL)?;
+            let rows = stmt.query_map([id], |row| {
+                Ok(LinkRevision {
+                    revision: row.get("revision")?,
+                    url: row.get("url")?,
+                    author: row.get("author")?,
+                    author_name: row.get("author_name")?,
+                    created_at: timestamp(row, "created_at")?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn get_link_revision(&self, id: &str, revision: i32) -> Result<Option<String>, Error> {
+        const SQL: &str = "SELECT url FROM link_revision WHERE link_id = ?1 AND revision = ?2";
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.query_row(SQL, params![id, revision], |row| row.get("url")).optional()
+        })
+        .await
+    }
+
+    async fn insert_clicks(&self, clicks: &[Click]) -> Result<u64, Error> {
+        const SQL: &str = "INSERT INTO click
+                (link_id, clicked_at, referer, user_agent, client_ip, accept_language, country)
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7 WHERE EXISTS (SELECT 1 FROM link WHERE id = ?1)";
+
+        let clicks = clicks.to_vec();
+        self.run(move |conn| {
+            let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut inserted = 0;
+            {
+                let mut stmt = transaction.prepare(SQL)?;
+                for click in clicks {
+                    inserted += stmt.execute(params![
+                        click.link_id,
+                        Timestamp(click.clicked_at),
+                        click.referer,
+                        click.user_agent,
+                        click.client_ip.map(|ip| ip.to_string()),
+                        click.accept_language,
+                        click.country,
+                    ])? as u64;
+                }
+            }
+            transaction.commit()?;
+            Ok(inserted)
+        })
+        .await
+    }
+
+    async fn count_clicks(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<ClickCount, Error> {
+        let sql = format!("SELECT {CLICK_COUNTS}");
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.query_row(&sql, params![id, Timestamp(from), Timestamp(to)], |row| {
+                Ok(ClickCount {
+                    clicks: row.get("clicks")?,
+                    unique_visitors: row.get("unique_visitors")?,
+                })
+            })
+        })
+        .await
+    }
+
+    async fn click_series(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        granularity: Granularity,
+    ) -> Result<Vec<ClickBucket>, Error> {
+        let sql = format!(
+            "SELECT unixepoch(strftime(?4, clicked_at / 1000000, 'unixepoch')) * 1000000 AS bucket,
+                {CLICK_COUNTS}
+            GROUP BY bucket ORDER BY bucket"
+        );
+        let format = match granularity {
+            Granularity::Hour => "%Y-%m-%d %H:00:00",
+            Granularity::Day => "%Y-%m-%d",
+            Granularity::Month => "%Y-%m-01",
+        };
+
+        let id = id.to_string();
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows =
+                stmt.query_map(params![id, Timestamp(from), Timestamp(to), format], |row| {
+                    Ok(ClickBucket {
+                        bucket: timestamp(row, "bucket")?,
+                        count: ClickCount {
+                            clicks: row.get("clicks")?,
+                            unique_visitors: row.get("unique_visitors")?,
+                        },
+                    })
+                })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn top_click_values(
+        &self,
+        id: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        dimension: ClickDimension,
+        limit: i64,
+    ) -> Result<Vec<ClickValue>, Error> {
+        const REFERER_SQL: &str = "SELECT referer AS value, count(*) AS clicks
+            FROM click WHERE link_id = ?1 AND clicked_at >= ?2 AND clicked_at < ?3
+            GROUP BY value ORDER BY clicks DESC, value NULLS LAST LIMIT ?4";
+        const USER_AGENT_SQL: &str = "SELECT user_agent AS value, count(*) AS clicks
+            FROM click WHERE link_id = ?1 AND clicked_at >= ?2 AND clicked_at < ?3
+            GROUP BY value ORDER BY clicks DESC, value NULLS LAST LIMIT ?4";
+        const COUNTRY_SQL: &str = "SELECT country AS value, count(*) AS clicks
+            FROM click WHERE link_id = ?1 AND clicked_at >= ?2 AND clicked_at < ?3
+            GROUP BY value ORDER BY clicks DESC, value NULLS LAST LIMIT ?4";
+
+        let sql = match dimension {
+            ClickDimension::Referer => REFERER_SQL,
+            ClickDimension::UserAgent => USER_AGENT_SQL,
+            ClickDimension::Country => COUNTRY_SQL,
+        };
+        let id = id.to_string();
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(sql)?;
+            let rows = stmt
+                .query_map(params![id, Timestamp(from), Timestamp(to), limit], |row| {
+                    Ok(ClickValue { value: row.get("value")?, clicks: row.get("clicks")? })
+                })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn purge_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        const SQL: &str = "DELETE FROM link WHERE expires_at <= ?1";
+
+        let before = Timestamp(OffsetDateTime::now_utc() - retention);
+        self.run(move |conn| Ok(conn.execute(SQL, [before])? as u64)).await
+    }
+
+    async fn archive_expired_links(&self, retention: Duration) -> Result<u64, Error> {
+        const ARCHIVE_SQL: &str = "INSERT INTO link_archive (id, data)
+            SELECT id, json_object(
+                    'id', id, 'url', url, 'expires_at', expires_at, 'max_clicks', max_clicks,
+                    'remaining_clicks', remaining_clicks, 'owner', owner,
+                    'redirect_type', redirect_type, 'forward_path', forward_path,
+                    'forward_query', forward_query, 'password_hash', password_hash,
+                    'created_at', created_at, 'version', version, 'deleted_at', deleted_at,
+                    'development_fields', json(development_fields),
+                    'revisions', (
+                        SELECT json_group_array(json_object(
+                                'revision', revision, 'url', url, 'author', author,
+                                'created_at', created_at))
+                        FROM (SELECT * FROM link_revision WHERE link_id = link.id
+                            ORDER BY revision)),
+                    'clicks', (
+                        SELECT json_group_array(json_object(
+                                'clicked_at', clicked_at, 'referer', referer,
+                                'user_agent', user_agent, 'client_ip', client_ip,
+                                'accept_language', accept_language, 'country', country))
+                        FROM (SELECT * FROM click WHERE link_id = link.id ORDER BY clicked_at)))
+            FROM link WHERE expires_at <= ?1";
+        const DELETE_SQL: &str = "DELETE FROM link WHERE expires_at <= ?1";
+
+        let before = Timestamp(OffsetDateTime::now_utc() - retention);
+        self.run(move |conn| {
+            let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transaction.execute(ARCHIVE_SQL, [&before])?;
+            let count = transaction.execute(DELETE_SQL, [&before])?;
+            transaction.commit()?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn purge_deleted_links(&self, retention: Duration) -> Result<u64, Error> {
+        const SQL: &str = "DELETE FROM link WHERE deleted_at <= ?1";
+
+        let before = Timestamp(OffsetDateTime::now_utc() - retention);
+        self.run(move |conn| Ok(conn.execute(SQL, [before])? as u64)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn archives_revisions_and_clicks() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(dir.path().join("links.db"), Duration::from_secs(5)).unwrap();
+        let expired_at = OffsetDateTime::now_utc() - time::Duration::minutes(1);
+        let link = NewLink {
+            id: "old",
+            url: "https://example.com/v1",
+            expires_at: Some(expired_at),
+            max_clicks: None,
+            owner: None,
+            redirect_type: None,
+            forward_path: false,
+            forward_query: QueryForwarding::Off,
+            password_hash: None,
+        };
+        store.create_link(&link, Duration::ZERO).await.unwrap();
+        let changes = LinkChanges { url: Some("https://example.com/v2"), ..Default::default() };
+        store.update_link("old", &changes, None, None).await.unwrap().unwrap();
+        let click = Click {
+            link_id: "old".to_string(),
+            clicked_at: expired_at,
+            referer: Some("https://referer.example/".to_string()),
+            user_agent: None,
+            client_ip: None,
+            accept_language: None,
+            country: None,
+        };
+        store.insert_clicks(&[click]).await.unwrap();
+
+        assert_eq!(store.archive_expired_links(Duration::ZERO).await.unwrap(), 1);
+        assert!(store.get_link_details("old").await.unwrap().is_none());
+        let conn = store.pool.get().unwrap();
+        let data: String = conn
+            .query_row("SELECT data FROM link_archive WHERE id = 'old'", [], |row| row.get(0))
+            .unwrap();
+        let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(data["url"], "https://example.com/v2");
+        let revisions = data["revisions"].as_array().unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0]["url"], "https://example.com/v1");
+        assert_eq!(data["clicks"][0]["referer"], "https://referer.example/");
+        let remaining: i64 = conn
+            .query_row("SELECT count(*) FROM click WHERE link_id = 'old'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
@@ -0,0 +1,72 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use tokio::task::JoinHandle;
+
+use crate::error::Error;
+use crate::store::LinkStore;
+
+/// What the sweeper does with links past their retention period.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ExpiredAction {
+    Purge,
+    #[default]
+    Archive,
+}
+
+impl FromStr for ExpiredAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "purge" => Ok(Self::Purge),
+            "archive" => Ok(Self::Archive),
+            _ => bail!("Unknown expired link action {s:?}, expected purge or archive"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SweeperConfig {
+    /// Time between two sweeps.
+    pub interval: Duration,
+    /// How long expired links are kept (answering `410 Gone`) before being swept.
+    pub retention: Duration,
+    pub action: ExpiredAction,
+    /// How long deleted links stay in the trash before being purged.
+    pub trash_retention: Duration,
+}
+
+/// Spawns a task that periodically removes expired links and purges the trash.
+pub fn spawn(store: Arc<dyn LinkStore>, config: SweeperConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match sweep(store.as_ref(), &config).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Swept {count} expired links ({:?})", config.action),
+                Err(err) => tracing::warn!(error = ?err, "Sweeping expired links failed"),
+            }
+            match purge_trash(store.as_ref(), &config).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Purged {count} deleted links"),
+                Err(err) => tracing::warn!(error = ?err, "Purging deleted links failed"),
+            }
+        }
+    })
+}
+
+async fn sweep(store: &dyn LinkStore, config: &SweeperConfig) -> Result<u64, Error> {
+    match config.action {
+        ExpiredAction::Purge => store.purge_expired_links(config.retention).await,
+        ExpiredAction::Archive => store.archive_expired_links(config.retention).await,
+    }
+}
+
+async fn purge_trash(store: &dyn LinkStore, config: &SweeperConfig) -> Result<u64, Error> {
+    store.purge_deleted_links(config.trash_retention).await
+}
@@ -0,0 +1,533 @@
+use std::net::{IpAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{
+    ACCEPT, CACHE_CONTROL, CONTENT_TYPE, COOKIE, ETAG, IF_MATCH, LOCATION, SET_COOKIE,
+};
+use reqwest::{Response, StatusCode};
+use serde_json::{Value, json};
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, Time};
+use url_shortener::api::{self, auth};
+use url_shortener::destination::QueryForwarding;
+use url_shortener::model::{Click, NewLink};
+use url_shortener::state::State;
+use url_shortener::store::{LinkStore, MemoryStore};
+
+/// The API served on a random local port, backed by a fresh in-memory store.
+struct TestApp {
+    address: String,
+    key: String,
+    store: Arc<dyn LinkStore>,
+    client: reqwest::Client,
+}
+
+impl TestApp {
+    async fn spawn() -> Self {
+        Self::spawn_with(|state| state).await
+    }
+
+    /// Like [`TestApp::spawn`], with the state adjusted by `configure`.
+    async fn spawn_with(configure: impl FnOnce(State) -> State) -> Self {
+        let store: Arc<dyn LinkStore> = Arc::new(MemoryStore::new());
+        let key = auth::generate_api_key();
+        store.create_api_key("test", &auth::hash_api_key(&key)).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(api::listen(listener, configure(State::new(store.clone()))).unwrap());
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        Self { address, key, store, client }
+    }
+
+    async fn create(&self, link: Value) -> Response {
+        let url = format!("{}/api/urls", self.address);
+        self.client.post(url).bearer_auth(&self.key).json(&link).send().await.unwrap()
+    }
+
+    async fn get(&self, path: &str) -> Response {
+        self.client.get(format!("{}{path}", self.address)).send().await.unwrap()
+    }
+
+    async fn post(&self, path: &str) -> Response {
+        let url = format!("{}{path}", self.address);
+        self.client.post(url).bearer_auth(&self.key).send().await.unwrap()
+    }
+
+    async fn authorized_get(&self, path: &str) -> Response {
+        let url = format!("{}{path}", self.address);
+        self.client.get(url).bearer_auth(&self.key).send().await.unwrap()
+    }
+
+    async fn update(&self, id: &str, if_match: &str, changes: Value) -> Response {
+        let url = format!("{}/api/urls/{id}", self.address);
+        let request = self.client.patch(url).bearer_auth(&self.key).header(IF_MATCH, if_match);
+        request.json(&changes).send().await.unwrap()
+    }
+}
+
+fn location(response: &Response) -> &str {
+    response.headers()[LOCATION].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn creates_and_redirects() {
+    let app = TestApp::spawn().await;
+
+    let response = app.create(json!({ "id": "docs", "url": "https://example.com/docs" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"1\"");
+    let link: Value = response.json().await.unwrap();
+    assert_eq!(link, json!({ "id": "docs", "url": "https://example.com/docs" }));
+
+    for path in ["/docs", "/api/urls/docs"] {
+        let response = app.get(path).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(location(&response), "https://example.com/docs");
+    }
+}
+
+#[tokio::test]
+async fn generates_missing_aliases() {
+    let app = TestApp::spawn().await;
+
+    let response = app.create(json!({ "url": "https://example.com/" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let link: Value = response.json().await.unwrap();
+    let id = link["id"].as_str().unwrap();
+    assert_eq!(id.len(), 7);
+
+    let response = app.get(&format!("/{id}")).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+}
+
+#[tokio::test]
+async fn requires_an_api_key_to_create_links() {
+    let app = TestApp::spawn().await;
+
+    let url = format!("{}/api/urls", app.address);
+    let link = json!({ "id": "docs", "url": "https://example.com/" });
+    let response = app.client.post(url).json(&link).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rejects_taken_aliases() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/docs" });
+    assert_eq!(app.create(link.clone()).await.status(), StatusCode::OK);
+    let response = app.create(link).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+}
+
+#[tokio::test]
+async fn answers_unknown_aliases_with_not_found() {
+    let app = TestApp::spawn().await;
+
+    assert_eq!(app.get("/missing").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn expired_links_are_gone() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "soon", "url": "https://example.com/", "ttl_seconds": 1 });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    assert_eq!(app.get("/soon").await.status(), StatusCode::FOUND);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(app.get("/soon").await.status(), StatusCode::GONE);
+}
+
+#[tokio::test]
+async fn exhausted_links_are_gone() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "once", "url": "https://example.com/", "max_clicks": 1 });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let response = app.get("/once").await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+    assert_eq!(app.get("/once").await.status(), StatusCode::GONE);
+}
+
+#[tokio::test]
+async fn updates_require_the_current_version() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/old" });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let changes = json!({ "url": "https://example.com/new" });
+    let response = app.update("docs", "\"2\"", changes.clone()).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(location(&app.get("/docs").await), "https://example.com/old");
+
+    let response = app.update("docs", "\"1\"", changes.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"2\"");
+    assert_eq!(location(&app.get("/docs").await), "https://example.com/new");
+
+    let response = app.update("docs", "\"1\"", changes).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn redirects_other_methods_of_unprotected_links() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "hook", "url": "https://example.com/hook", "redirect_type": 307 });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let url = format!("{}/hook", app.address);
+    let response = app.client.post(url).json(&json!({ "event": "push" })).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(location(&response), "https://example.com/hook");
+}
+
+#[tokio::test]
+async fn keeps_unlocked_redirects_private() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({
+        "id": "secret",
+        "url": "https://example.com/secret",
+        "redirect_type": 308,
+        "password": "hunter2",
+    });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    assert_eq!(app.get("/secret").await.status(), StatusCode::OK);
+
+    let url = format!("{}/secret", app.address);
+    let response = app.client.post(&url).form(&[("password", "wrong")]).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.client.post(&url).form(&[("password", "hunter2")]).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    let cookie = cookie.split(';').next().unwrap().to_string();
+
+    let response = app.client.get(&url).header(COOKIE, cookie).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[CACHE_CONTROL], "private, no-store");
+}
+
+#[tokio::test]
+async fn rejects_forwarded_paths_leaving_the_destination() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/docs", "forward_path": true });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let response = app.get("/docs/guide").await;
+    assert_eq!(location(&response), "https://example.com/docs/guide");
+    let response = app.get("/docs/..%5C..%5Cadmin").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn only_forwards_posts_to_short_links() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "hook", "url": "https://example.com/hook", "redirect_type": 307 });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let url = format!("{}/hook", app.address);
+    let response = app.client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let url = format!("{}/api/urls/hook", app.address);
+    for method in [reqwest::Method::DELETE, reqwest::Method::PUT] {
+        let response = app.client.request(method, &url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
+
+#[tokio::test]
+async fn pages_through_links_in_every_order() {
+    let app = TestApp::spawn().await;
+
+    for id in ["link-a", "link-b", "link-c", "link-d", "link-e"] {
+        let link = json!({ "id": id, "url": "https://example.com/" });
+        assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    }
+    // No link has been clicked, so sorting by clicks ties everywhere and falls back to the alias
+    let orders = [
+        ("created_at", "asc", ["link-a", "link-b", "link-c", "link-d", "link-e"]),
+        ("created_at", "desc", ["link-e", "link-d", "link-c", "link-b", "link-a"]),
+        ("clicks", "asc", ["link-a", "link-b", "link-c", "link-d", "link-e"]),
+        ("clicks", "desc", ["link-e", "link-d", "link-c", "link-b", "link-a"]),
+    ];
+    for (sort, order, expected) in orders {
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let mut query = vec![("sort", sort), ("order", order), ("limit", "2")];
+            query.extend(cursor.as_deref().map(|cursor| ("cursor", cursor)));
+            let url = format!("{}/api/urls", app.address);
+            let request = app.client.get(url).bearer_auth(&app.key).query(&query);
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page: Value = response.json().await.unwrap();
+            let links = page["links"].as_array().unwrap();
+            ids.extend(links.iter().map(|link| link["id"].as_str().unwrap().to_string()));
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        assert_eq!(ids, expected, "{sort} {order}");
+    }
+}
+
+#[tokio::test]
+async fn rejects_malformed_cursors() {
+    let app = TestApp::spawn().await;
+
+    for id in ["link-a", "link-b"] {
+        let link = json!({ "id": id, "url": "https://example.com/" });
+        assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    }
+    let url = format!("{}/api/urls", app.address);
+    let response = app.client.get(&url).bearer_auth(&app.key).query(&[("limit", "1")]);
+    let page: Value = response.send().await.unwrap().json().await.unwrap();
+    let cursor = page["next_cursor"].as_str().unwrap();
+
+    for query in [
+        [("cursor", "not-a-cursor"), ("sort", "created_at")],
+        [("cursor", cursor), ("sort", "clicks")],
+    ] {
+        let response = app.client.get(&url).bearer_auth(&app.key).query(&query);
+        let response = response.send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = response.json().await.unwrap();
+        assert_eq!(problem["field"], "cursor");
+    }
+}
+
+#[tokio::test]
+async fn rolls_back_to_earlier_destinations() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/v1" });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    let changes = json!({ "url": "https://example.com/v2" });
+    assert_eq!(app.update("docs", "\"1\"", changes).await.status(), StatusCode::OK);
+
+    let response = app.authorized_get("/api/urls/docs/history").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let history: Value = response.json().await.unwrap();
+    let revisions: Vec<_> = history["revisions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|revision| (revision["revision"].as_i64().unwrap(), revision["url"].clone()))
+        .collect();
+    assert_eq!(
+        revisions,
+        [(2, json!("https://example.com/v2")), (1, json!("https://example.com/v1"))]
+    );
+
+    let response = app.post("/api/urls/docs/rollback/1").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"3\"");
+    assert_eq!(location(&app.get("/docs").await), "https://example.com/v1");
+    let history: Value = app.authorized_get("/api/urls/docs/history").await.json().await.unwrap();
+    assert_eq!(history["revisions"][0]["revision"], 3);
+
+    assert_eq!(app.post("/api/urls/docs/rollback/9").await.status(), StatusCode::NOT_FOUND);
+    let response = app.client.post(format!("{}/api/urls/docs/rollback/2", app.address));
+    assert_eq!(response.send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn checks_rolled_back_destinations_against_the_policy() {
+    let app = TestApp::spawn().await;
+
+    // Stored directly, as a destination accepted before the policy changed
+    let link = NewLink {
+        id: "docs",
+        url: "javascript:alert(1)",
+        expires_at: None,
+        max_clicks: None,
+        owner: None,
+        redirect_type: None,
+        forward_path: false,
+        forward_query: QueryForwarding::Off,
+        password_hash: None,
+    };
+    app.store.create_link(&link, Duration::ZERO).await.unwrap();
+    let changes = json!({ "url": "https://example.com/" });
+    assert_eq!(app.update("docs", "\"1\"", changes).await.status(), StatusCode::OK);
+
+    let response = app.post("/api/urls/docs/rollback/1").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(location(&app.get("/docs").await), "https://example.com/");
+}
+
+#[tokio::test]
+async fn restores_deleted_links_and_quarantines_their_aliases() {
+    let app =
+        TestApp::spawn_with(|state| state.with_alias_quarantine(Duration::from_secs(3600))).await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/docs" });
+    assert_eq!(app.create(link.clone()).await.status(), StatusCode::OK);
+    let url = format!("{}/api/urls/delete", app.address);
+    let request = app.client.delete(url).bearer_auth(&app.key).json(&json!({ "id": "docs" }));
+    assert_eq!(request.send().await.unwrap().status(), StatusCode::OK);
+    assert_eq!(app.get("/docs").await.status(), StatusCode::NOT_FOUND);
+
+    let trash: Value = app.authorized_get("/api/urls/trash").await.json().await.unwrap();
+    assert_eq!(trash["links"][0]["id"], "docs");
+    let links: Value = app.authorized_get("/api/urls").await.json().await.unwrap();
+    assert_eq!(links["links"], json!([]));
+    // The alias stays reserved for its owner while it is in the trash
+    assert_eq!(app.create(link).await.status(), StatusCode::CONFLICT);
+
+    let response = app.post("/api/urls/docs/restore").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"2\"");
+    assert_eq!(location(&app.get("/docs").await), "https://example.com/docs");
+    assert_eq!(app.post("/api/urls/docs/restore").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn previews_links_without_following_them() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/docs", "max_clicks": 1 });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let response = app.get("/docs+").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+    assert!(response.text().await.unwrap().contains("https://example.com/docs"));
+
+    let url = format!("{}/docs?preview=1", app.address);
+    let response = app.client.get(url).header(ACCEPT, "application/json").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+    let preview: Value = response.json().await.unwrap();
+    assert_eq!(preview["id"], "docs");
+    assert_eq!(preview["destination"], "https://example.com/docs");
+    assert_eq!(preview["status"], "active");
+    assert_eq!(preview["remaining_clicks"], 1);
+
+    // Previews leave the only click of the link to the redirect
+    assert_eq!(app.get("/docs").await.status(), StatusCode::FOUND);
+    assert_eq!(app.get("/docs").await.status(), StatusCode::GONE);
+    assert_eq!(app.get("/missing+").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn hides_destinations_of_locked_links_from_previews() {
+    let app = TestApp::spawn().await;
+
+    let link =
+        json!({ "id": "secret", "url": "https://example.com/secret", "password": "hunter2" });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+
+    let response = app.get("/secret+").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.text().await.unwrap().contains("https://example.com/secret"));
+    let url = format!("{}/secret+", app.address);
+    let response = app.client.get(url).header(ACCEPT, "application/json").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn keeps_reserved_paths_out_of_aliases() {
+    let app = TestApp::spawn().await;
+
+    for id in ["api", "Health", "trash"] {
+        let response = app.create(json!({ "id": id, "url": "https://example.com/" })).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY, "{id}");
+        let problem: Value = response.json().await.unwrap();
+        assert_eq!((&problem["field"], &problem["rule"]), (&json!("id"), &json!("reserved")));
+    }
+
+    assert_eq!(app.get("/health").await.status(), StatusCode::OK);
+    let response = app.get("/robots.txt").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+    assert_eq!(app.get("/favicon.ico").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn aggregates_link_statistics() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/docs" });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    let day = OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT) - time::Duration::days(1);
+    let click = |minutes, ip, referer: &str| Click {
+        link_id: "docs".to_string(),
+        clicked_at: day + time::Duration::minutes(minutes),
+        referer: Some(referer.to_string()),
+        user_agent: Some("test".to_string()),
+        client_ip: Some(IpAddr::from([192, 0, 2, ip])),
+        accept_language: None,
+        country: Some("NL".to_string()),
+    };
+    let clicks = [
+        click(10, 1, "https://one.example/"),
+        click(20, 1, "https://one.example/"),
+        click(70, 2, "https://two.example/"),
+    ];
+    app.store.insert_clicks(&clicks).await.unwrap();
+
+    let url = format!("{}/api/urls/docs/stats", app.address);
+    let to = day + time::Duration::days(1);
+    let query = [
+        ("from", day.format(&Rfc3339).unwrap()),
+        ("to", to.format(&Rfc3339).unwrap()),
+        ("granularity", "hour".to_string()),
+    ];
+    let response = app.client.get(&url).bearer_auth(&app.key).query(&query);
+    let response = response.send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats: Value = response.json().await.unwrap();
+    assert_eq!((&stats["total_clicks"], &stats["unique_visitors"]), (&json!(3), &json!(2)));
+    let series: Vec<_> = stats["series"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| {
+            (bucket["clicks"].as_i64().unwrap(), bucket["unique_visitors"].as_i64().unwrap())
+        })
+        .collect();
+    assert_eq!(series, [(2, 1), (1, 1)]);
+    assert_eq!(stats["top_referers"][0], json!({ "value": "https://one.example/", "clicks": 2 }));
+    assert_eq!(stats["top_countries"], json!([{ "value": "NL", "clicks": 3 }]));
+
+    let response = app.client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn shows_links_with_their_etag() {
+    let app = TestApp::spawn().await;
+
+    let link = json!({ "id": "docs", "url": "https://example.com/old" });
+    assert_eq!(app.create(link).await.status(), StatusCode::OK);
+    let changes = json!({ "url": "https://example.com/new" });
+    assert_eq!(app.update("docs", "\"1\"", changes).await.status(), StatusCode::OK);
+
+    let response = app.authorized_get("/api/urls/docs/info").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+    assert_eq!(etag, "\"2\"");
+    let link: Value = response.json().await.unwrap();
+    assert_eq!((&link["url"], &link["version"]), (&json!("https://example.com/new"), &json!(2)));
+
+    let changes = json!({ "max_clicks": 5 });
+    assert_eq!(app.update("docs", &etag, changes).await.status(), StatusCode::OK);
+    assert_eq!(app.authorized_get("/api/urls/missing/info").await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(app.get("/api/urls/docs/info").await.status(), StatusCode::UNAUTHORIZED);
+}